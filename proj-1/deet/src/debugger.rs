@@ -1,8 +1,44 @@
-use crate::debugger_command::DebuggerCommand;
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
-use crate::inferior::{Inferior, Status};
+use crate::debugger_command::{DebuggerCommand, ExamineFormat};
+use crate::dwarf_data::{
+    decode_integer, DwarfData, Encoding, Error as DwarfError, Line, Location, Type, TypeKind,
+    Variable,
+};
+use crate::expression::{self, Expression, Value};
+use crate::inferior::{
+    FollowForkMode, Frame, Inferior, Mapping, ReadError, Status, StopFilter, REGISTER_NAMES,
+};
+use iced_x86::{Decoder, DecoderOptions, Formatter, GasFormatter, Instruction, OpKind};
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::fs;
+use std::rc::Rc;
+
+/// How many lines of source `list` prints at a time
+const LIST_WINDOW: usize = 10;
+/// The longest an x86-64 instruction can be.
+const MAX_INSTRUCTION_LENGTH: usize = 15;
+/// How many instructions `x/i` decodes from each read of the inferior's memory.
+const EXAMINE_CHUNK: usize = 64;
+/// Strings printed by `x/s` are cut off after this many bytes.
+const MAX_STRING_LENGTH: usize = 200;
+/// Accesses below this address are taken to be through a NULL pointer (plus some offset).
+const NULL_PAGE_SIZE: usize = 4096;
+/// The bits of eflags worth decoding, and their names.
+const EFLAGS: [(u32, &str); 9] = [
+    (0, "CF"),
+    (2, "PF"),
+    (4, "AF"),
+    (6, "ZF"),
+    (7, "SF"),
+    (8, "TF"),
+    (9, "IF"),
+    (10, "DF"),
+    (11, "OF"),
+];
 
 fn parse_address(addr: &str) -> Option<usize> {
     if !addr.starts_with('*') {
@@ -17,46 +53,244 @@ fn parse_address(addr: &str) -> Option<usize> {
     usize::from_str_radix(addr_without_0x, 16).ok()
 }
 
+struct Breakpoint {
+    addr: usize,
+    enabled: bool,
+    hit_count: usize,
+    condition: Option<Expression>,
+    ignore_count: usize,
+}
+
+/// What happens when the inferior receives a signal, as set with `handle`.
+#[derive(Clone, Copy)]
+struct SignalPolicy {
+    /// Whether to stop the inferior and return to the prompt
+    stop: bool,
+    /// Whether to mention the signal
+    print: bool,
+    /// Whether to deliver the signal to the inferior when it is resumed
+    pass: bool,
+}
+
+impl SignalPolicy {
+    /// Follows gdb's defaults: signals that are part of normal operation are passed on silently,
+    /// SIGINT, SIGTRAP and SIGSTOP are meant for the debugger, and any other signal stops the
+    /// inferior before it is delivered.
+    fn default_for(signal: Signal) -> SignalPolicy {
+        match signal {
+            Signal::SIGALRM
+            | Signal::SIGURG
+            | Signal::SIGCHLD
+            | Signal::SIGWINCH
+            | Signal::SIGIO
+            | Signal::SIGPROF
+            | Signal::SIGVTALRM => SignalPolicy {
+                stop: false,
+                print: false,
+                pass: true,
+            },
+            Signal::SIGINT | Signal::SIGTRAP | Signal::SIGSTOP => SignalPolicy {
+                stop: true,
+                print: true,
+                pass: false,
+            },
+            _ => SignalPolicy {
+                stop: true,
+                print: true,
+                pass: true,
+            },
+        }
+    }
+}
+
+struct Watchpoint {
+    name: String,
+    /// Like breakpoint addresses, relative to where the executable is linked rather than loaded
+    addr: usize,
+    entity_type: Type,
+    /// Which debug register (DR0-DR3) the watchpoint occupies
+    slot: usize,
+    /// Whether the variable lives at a fixed address, so that the watchpoint can be carried over
+    /// when the inferior is restarted
+    global: bool,
+    /// The frame whose locals the expression involves, if any: once it returns, the watched
+    /// memory may be reused by other frames, so the watchpoint is deleted
+    scope: Option<WatchScope>,
+    old_value: Vec<u8>,
+    hit_count: usize,
+}
+
+#[derive(Clone, Copy)]
+struct WatchScope {
+    /// Number of the thread running the frame
+    thread: usize,
+    /// The frame's CFA, which is where the stack pointer is once it has returned
+    cfa: usize,
+    /// The frame's return address, relative to where the executable is linked. A breakpoint is
+    /// kept there to notice when the frame returns.
+    return_addr: usize,
+}
+
+/// Evaluates expressions in the context of one frame of the stopped inferior.
+struct FrameContext<'a> {
+    inferior: &'a Inferior,
+    debug_data: &'a DwarfData,
+    frame: Frame,
+}
+
+impl expression::Context for FrameContext<'_> {
+    fn debug_data(&self) -> &DwarfData {
+        self.debug_data
+    }
+
+    fn variable(&self, name: &str) -> Result<Value, String> {
+        let pc = self
+            .frame
+            .lookup_pc()
+            .wrapping_sub(self.inferior.load_bias());
+        let var = match self.debug_data.get_variable(pc, name) {
+            Some(var) => var,
+            None => {
+                let (type_id, value) = self
+                    .debug_data
+                    .find_enumerator(name)
+                    .ok_or_else(|| format!("No symbol \"{}\" in current context.", name))?;
+                let enum_type = self.debug_data.get_type(type_id).unwrap();
+                return Ok(Value {
+                    value_type: enum_type.clone(),
+                    type_id: Some(type_id),
+                    bytes: value.to_le_bytes()[..enum_type.size.min(8)].to_vec(),
+                    address: None,
+                });
+            }
+        };
+        let bytes = match self
+            .inferior
+            .read_variable(var, &self.frame, self.debug_data)
+        {
+            Ok(bytes) => bytes,
+            Err(ReadError::Ptrace(err)) => {
+                return Err(format!("Cannot access memory for {}: {}", name, err))
+            }
+            Err(err) => return Err(format!("Cannot read {}: {}", name, err)),
+        };
+        Ok(Value {
+            value_type: var.entity_type.clone(),
+            type_id: Some(var.type_id),
+            bytes,
+            address: self
+                .inferior
+                .variable_address(var, &self.frame, self.debug_data)
+                .ok(),
+        })
+    }
+
+    fn register(&self, name: &str) -> Result<Value, String> {
+        let value = match self.inferior.register_value(&self.frame, name) {
+            Ok(value) => value,
+            Err(ReadError::UnknownRegister(name)) => {
+                return Err(format!("Invalid register `{}'", name))
+            }
+            Err(err) => return Err(format!("Cannot read ${}: {}", name, err)),
+        };
+        // Registers holding code and stack addresses are shown as pointers, like gdb does
+        let value_type = match name {
+            "pc" | "rip" | "sp" | "rsp" | "fp" | "rbp" => {
+                Type::new("void *".to_string(), 8, TypeKind::Pointer(None))
+            }
+            _ => Type::new("long".to_string(), 8, TypeKind::Base(Encoding::Signed)),
+        };
+        Ok(Value {
+            value_type,
+            type_id: None,
+            bytes: value.to_le_bytes().to_vec(),
+            address: None,
+        })
+    }
+
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+        self.inferior
+            .read_bytes(addr, len)
+            .map_err(|err| format!("Cannot access memory at address {:#x}: {}", addr, err))
+    }
+}
+
+/// Loads and prints the debugging symbols for a binary, or returns an error message.
+fn load_debug_data(target: &str) -> Result<DwarfData, String> {
+    let debug_data = match DwarfData::from_file(target) {
+        Ok(val) => val,
+        Err(DwarfError::ErrorOpeningFile) => return Err(format!("Could not open file {}", target)),
+        Err(DwarfError::DwarfFormatError(err)) => {
+            return Err(format!(
+                "Could not load debugging symbols from {}: {:?}",
+                target, err
+            ))
+        }
+    };
+
+    debug_data.print();
+    Ok(debug_data)
+}
+
 pub struct Debugger {
-    breakpoints: Vec<usize>,
-    debug_data: DwarfData,
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    watchpoints: BTreeMap<usize, Watchpoint>,
+    /// Watchpoints that went out of scope while the inferior was running, which still have to be
+    /// disarmed
+    expired_watchpoints: Vec<Watchpoint>,
+    next_breakpoint: usize,
+    /// Shared with the operations that run the inferior, which consult the breakpoints while the
+    /// debugger is borrowed to filter stops
+    debug_data: Rc<DwarfData>,
     target: String,
     history_path: String,
     readline: Editor<()>,
     inferior: Option<Inferior>,
+    /// Level of the frame that print and info locals look at (0 is the innermost frame). Reset
+    /// whenever the inferior stops.
+    selected_frame: usize,
+    /// Where a bare `list` picks up from: a source file and the first line not listed yet. Cleared
+    /// whenever the inferior stops or another frame is selected.
+    list_position: Option<(String, usize)>,
+    /// Which process to keep debugging when the inferior forks
+    follow_fork_mode: FollowForkMode,
+    /// Whether to detach from the process not followed after a fork, rather than keep it stopped
+    detach_on_fork: bool,
+    signal_policies: HashMap<Signal, SignalPolicy>,
 }
 
 impl Debugger {
     /// Initializes the debugger.
     pub fn new(target: &str) -> Debugger {
         // TODO (milestone 3): initialize the DwarfData
-        let debug_data = match DwarfData::from_file(target) {
+        let debug_data = match load_debug_data(target) {
             Ok(val) => val,
-            Err(DwarfError::ErrorOpeningFile) => {
-                println!("Could not open file {}", target);
-                std::process::exit(1);
-            }
-            Err(DwarfError::DwarfFormatError(err)) => {
-                println!(
-                    "Could not load debugging symbols from {}: {:?}",
-                    target, err
-                );
+            Err(msg) => {
+                println!("{}", msg);
                 std::process::exit(1);
             }
         };
 
-        debug_data.print();
-
         let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
         let mut readline = Editor::<()>::new();
         // Attempt to load history from ~/.deet_history if it exists
         let _ = readline.load_history(&history_path);
 
         Debugger {
-            breakpoints: Vec::new(),
-            debug_data,
+            breakpoints: BTreeMap::new(),
+            watchpoints: BTreeMap::new(),
+            expired_watchpoints: Vec::new(),
+            next_breakpoint: 0,
+            debug_data: Rc::new(debug_data),
             history_path,
             inferior: None,
+            selected_frame: 0,
+            list_position: None,
+            follow_fork_mode: FollowForkMode::Parent,
+            detach_on_fork: true,
+            signal_policies: Signal::iterator()
+                .map(|signal| (signal, SignalPolicy::default_for(signal)))
+                .collect(),
             readline,
             target: target.to_string(),
         }
@@ -65,31 +299,145 @@ impl Debugger {
     pub fn run(&mut self) {
         loop {
             match self.get_next_command() {
+                DebuggerCommand::Attach(pid) => {
+                    if self.inferior.is_some() {
+                        println!("An inferior is already being debugged. Detach from it first.");
+                    } else {
+                        match load_debug_data(&format!("/proc/{}/exe", pid)) {
+                            Ok(debug_data) => self.attach(pid, Some(debug_data)),
+                            Err(msg) => println!("{}", msg),
+                        }
+                    }
+                }
                 DebuggerCommand::Backtrace => {
-                    if let Some(inferior) = &self.inferior {
-                        inferior.print_backtrace(&self.debug_data).unwrap();
+                    if self.inferior.is_some() {
+                        self.print_backtrace();
                     } else {
                         println!("There is no inferior running.");
                     }
                 }
-                DebuggerCommand::Breakpoint(arg) => {
-                    let mut addr = parse_address(&arg);
-                    if addr.is_none() {
-                        addr = self.debug_data.get_addr_for_function(None, &arg);
-                    }
-                    if addr.is_none() {
-                        addr = self
-                            .debug_data
-                            .get_addr_for_line(None, arg.parse().unwrap_or(0));
+                DebuggerCommand::Breakpoint(arg, condition) => {
+                    let condition = match condition.map(|cond| Expression::parse(&cond)) {
+                        Some(Ok(condition)) => Some(condition),
+                        Some(Err(err)) => {
+                            println!("{}", err);
+                            continue;
+                        }
+                        None => None,
+                    };
+                    match self.breakpoint_address(&arg) {
+                        Ok(addr) => {
+                            println!(
+                                "Set breakpoint {} at {:#x}",
+                                self.next_breakpoint,
+                                addr.wrapping_add(self.load_bias())
+                            );
+                            self.breakpoints.insert(
+                                self.next_breakpoint,
+                                Breakpoint {
+                                    addr,
+                                    enabled: true,
+                                    hit_count: 0,
+                                    condition,
+                                    ignore_count: 0,
+                                },
+                            );
+                            self.next_breakpoint += 1;
+                            self.sync_breakpoint(addr);
+                        }
+                        Err(err) => println!("{}", err),
                     }
-                    if let Some(addr) = addr {
-                        println!("Set breakpoint {} at {}", self.breakpoints.len(), addr);
-                        self.breakpoints.push(addr);
+                }
+                DebuggerCommand::Delete(num) => {
+                    if let Some(breakpoint) = self.breakpoints.remove(&num) {
+                        self.sync_breakpoint(breakpoint.addr);
+                    } else if let Some(watchpoint) = self.watchpoints.remove(&num) {
                         if let Some(inferior) = &mut self.inferior {
-                            inferior.set_breakpoint(addr).unwrap();
+                            if let Err(err) = inferior.clear_watchpoint(watchpoint.slot) {
+                                println!("Could not clear watchpoint {}: {}", num, err);
+                            }
                         }
+                        if let Some(scope) = watchpoint.scope {
+                            self.sync_breakpoint(scope.return_addr);
+                        }
+                    } else {
+                        println!("No breakpoint number {}.", num);
+                    }
+                }
+                DebuggerCommand::Disassemble(function) => {
+                    if self.inferior.is_none() {
+                        println!("There is no inferior running.");
+                    } else if let Err(err) = self.disassemble(function.as_deref()) {
+                        println!("{}", err);
+                    }
+                }
+                DebuggerCommand::Detach => match self.inferior.take() {
+                    Some(mut inferior) => match inferior.detach() {
+                        Ok(()) => println!("Detached from process {}", inferior.pid()),
+                        Err(err) => {
+                            println!("Could not detach from process: {}", err);
+                            self.inferior = Some(inferior);
+                        }
+                    },
+                    None => println!("There is no inferior running."),
+                },
+                DebuggerCommand::Down(count) => {
+                    if self.inferior.is_none() {
+                        println!("No stack.");
+                    } else if self.selected_frame == 0 {
+                        println!("Bottom (innermost) frame selected; you cannot go down.");
                     } else {
-                        println!("Invalid argument.");
+                        self.select_frame(self.selected_frame.saturating_sub(count));
+                    }
+                }
+                DebuggerCommand::Disable(num) => self.set_breakpoint_enabled(num, false),
+                DebuggerCommand::Enable(num) => self.set_breakpoint_enabled(num, true),
+                DebuggerCommand::Frame(level) => {
+                    if self.inferior.is_none() {
+                        println!("No stack.");
+                    } else {
+                        self.select_frame(level.unwrap_or(self.selected_frame));
+                    }
+                }
+                DebuggerCommand::Handle(signal, actions) => self.handle_signal(signal, &actions),
+                DebuggerCommand::Ignore(num, count) => match self.breakpoints.get_mut(&num) {
+                    Some(breakpoint) => {
+                        breakpoint.ignore_count = count;
+                        println!(
+                            "Will ignore next {} crossings of breakpoint {}.",
+                            count, num
+                        );
+                    }
+                    None => println!("No breakpoint number {}.", num),
+                },
+                DebuggerCommand::InfoBreakpoints => self.print_breakpoints(),
+                DebuggerCommand::InfoRegisters(names) => {
+                    if self.inferior.is_none() {
+                        println!("The program has no registers now.");
+                    } else {
+                        self.print_registers(&names);
+                    }
+                }
+                DebuggerCommand::InfoSignals(signal) => self.print_signal_policies(signal),
+                DebuggerCommand::InfoThreads => {
+                    if self.inferior.is_none() {
+                        println!("No threads.");
+                    } else {
+                        self.print_threads();
+                    }
+                }
+                DebuggerCommand::Examine(format, addr) => {
+                    if self.inferior.is_none() {
+                        println!("There is no inferior running.");
+                    } else if let Err(err) = self.examine(format, &addr) {
+                        println!("{}", err);
+                    }
+                }
+                DebuggerCommand::InfoLocals => {
+                    if self.inferior.is_none() {
+                        println!("No frame selected.");
+                    } else {
+                        self.print_locals();
                     }
                 }
                 DebuggerCommand::Continue => {
@@ -99,28 +447,132 @@ impl Debugger {
                         self.inferior_continue_exec();
                     }
                 }
+                DebuggerCommand::Finish => {
+                    if self.inferior.is_none() {
+                        println!("There is no inferior running.");
+                    } else {
+                        self.finish();
+                    }
+                }
+                DebuggerCommand::List(arg) => self.list_source(arg.as_deref()),
+                DebuggerCommand::Next => {
+                    if self.inferior.is_none() {
+                        println!("There is no inferior running.");
+                    } else {
+                        self.resume_inferior(|inferior, debug_data, filter| {
+                            inferior.step_line(debug_data, false, filter)
+                        });
+                    }
+                }
+                DebuggerCommand::NextInstruction(count) => {
+                    if self.inferior.is_none() {
+                        println!("There is no inferior running.");
+                    } else {
+                        self.step_instructions(count, false);
+                    }
+                }
+                DebuggerCommand::Print(input) => {
+                    if self.inferior.is_none() {
+                        println!("There is no inferior running.");
+                    } else {
+                        self.print_expression(&input);
+                    }
+                }
+                DebuggerCommand::SetVariable(target, value) => {
+                    if self.inferior.is_none() {
+                        println!("There is no inferior running.");
+                    } else if let Err(err) = self.set_variable(&target, &value) {
+                        println!("{}", err);
+                    }
+                }
                 DebuggerCommand::Run(args) => {
-                    if let Some(inferior) = &mut self.inferior {
-                        inferior
-                            .kill()
-                            .expect("Failed to kill the former inferior.");
+                    if let Some(mut inferior) = self.inferior.take() {
+                        if let Err(err) = inferior.kill() {
+                            println!("Could not kill the former inferior: {}", err);
+                        }
                     }
 
                     if let Some(mut inferior) = Inferior::new(&self.target, &args) {
-                        for breakpoint in &self.breakpoints {
-                            inferior
-                                .set_breakpoint(*breakpoint)
-                                .expect("Failed to set breakpoint");
-                        }
+                        self.install_breakpoints(&mut inferior);
                         self.inferior = Some(inferior);
                         self.inferior_continue_exec();
                     } else {
                         println!("Unable to start subprocess");
                     }
                 }
+                DebuggerCommand::SetDetachOnFork(detach_on_fork) => {
+                    self.detach_on_fork = detach_on_fork;
+                    if let Some(inferior) = &mut self.inferior {
+                        inferior.set_fork_policy(self.follow_fork_mode, detach_on_fork);
+                    }
+                }
+                DebuggerCommand::SetFollowForkMode(mode) => {
+                    self.follow_fork_mode = mode;
+                    if let Some(inferior) = &mut self.inferior {
+                        inferior.set_fork_policy(mode, self.detach_on_fork);
+                    }
+                }
+                DebuggerCommand::Step => {
+                    if self.inferior.is_none() {
+                        println!("There is no inferior running.");
+                    } else {
+                        self.resume_inferior(|inferior, debug_data, filter| {
+                            inferior.step_line(debug_data, true, filter)
+                        });
+                    }
+                }
+                DebuggerCommand::StepInstruction(count) => {
+                    if self.inferior.is_none() {
+                        println!("There is no inferior running.");
+                    } else {
+                        self.step_instructions(count, true);
+                    }
+                }
+                DebuggerCommand::Thread(num) => match &mut self.inferior {
+                    None => println!("No thread selected."),
+                    Some(inferior) => match num {
+                        None => println!(
+                            "[Current thread is {} (LWP {})]",
+                            inferior.current_thread(),
+                            inferior.threads()[&inferior.current_thread()]
+                        ),
+                        Some(num) if inferior.select_thread(num) => {
+                            println!(
+                                "[Switching to thread {} (LWP {})]",
+                                num,
+                                inferior.threads()[&num]
+                            );
+                            self.selected_frame = 0;
+                            self.list_position = None;
+                            self.select_frame(0);
+                        }
+                        Some(num) => println!("Invalid thread ID: {}", num),
+                    },
+                },
+                DebuggerCommand::Up(count) => {
+                    if self.inferior.is_none() {
+                        println!("No stack.");
+                    } else {
+                        self.frame_up(count);
+                    }
+                }
+                DebuggerCommand::Watch(name) => {
+                    if self.inferior.is_none() {
+                        println!("There is no inferior running.");
+                    } else {
+                        self.set_watchpoint(&name);
+                    }
+                }
                 DebuggerCommand::Quit => {
                     if let Some(inferior) = &mut self.inferior {
-                        inferior.kill().expect("Failed to kill inferior");
+                        let result = if inferior.is_attached() {
+                            inferior.detach()
+                        } else {
+                            inferior.kill()
+                        };
+                        if let Err(err) = result {
+                            println!("Could not release the inferior: {}", err);
+                        }
                     }
                     return;
                 }
@@ -128,6 +580,119 @@ impl Debugger {
         }
     }
 
+    /// Attaches to a running process. Once that has worked, the debugger switches to
+    /// `debug_data`, the symbols loaded from /proc/<pid>/exe, unless they are already in use.
+    pub fn attach(&mut self, pid: i32, debug_data: Option<DwarfData>) {
+        match Inferior::attach(Pid::from_raw(pid)) {
+            Ok(mut inferior) => {
+                // Remember the real path of the binary so that `run` can start it again later
+                let exe = format!("/proc/{}/exe", pid);
+                let target = std::fs::read_link(&exe)
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or(exe);
+                match debug_data {
+                    Some(debug_data) => self.replace_target(&target, debug_data),
+                    None => self.target = target,
+                }
+                self.install_breakpoints(&mut inferior);
+                println!("Attached to process {}", pid);
+                let rip = inferior.get_pc();
+                self.inferior = Some(inferior);
+                self.selected_frame = 0;
+                if let Ok(rip) = rip {
+                    self.print_location(rip);
+                }
+            }
+            Err(err) => println!("Could not attach to process {}: {}", pid, err),
+        }
+    }
+
+    /// Loads the debugging symbols of another binary and switches to it, returning whether that
+    /// worked.
+    fn load_target(&mut self, target: &str) -> bool {
+        match load_debug_data(target) {
+            Ok(debug_data) => {
+                self.replace_target(target, debug_data);
+                true
+            }
+            Err(msg) => {
+                println!("{}", msg);
+                false
+            }
+        }
+    }
+
+    /// Replaces the debugging symbols with those of another binary. Breakpoints and watchpoints
+    /// refer to addresses in the old binary, so they are discarded if the binary changes.
+    fn replace_target(&mut self, target: &str, debug_data: DwarfData) {
+        let same_binary =
+            std::fs::canonicalize(target).ok() == std::fs::canonicalize(&self.target).ok();
+        let has_breakpoints = !self.breakpoints.is_empty() || !self.watchpoints.is_empty();
+        if !same_binary && has_breakpoints {
+            println!(
+                "Deleting breakpoints and watchpoints set in {}",
+                self.target
+            );
+            self.breakpoints.clear();
+            self.watchpoints.clear();
+        }
+        self.debug_data = Rc::new(debug_data);
+        self.target = target.to_string();
+    }
+
+    /// Installs all enabled breakpoints and watchpoints into a freshly started (or attached)
+    /// inferior. Watchpoints on anything but global variables (such as locals, whose frame no
+    /// longer exists) are deleted, as are watchpoints that can't be set again.
+    fn install_breakpoints(&mut self, inferior: &mut Inferior) {
+        inferior.set_fork_policy(self.follow_fork_mode, self.detach_on_fork);
+        for (&signal, policy) in &self.signal_policies {
+            inferior.set_signal_pass(signal, policy.pass);
+        }
+        for (num, breakpoint) in self.breakpoints.iter().filter(|(_, bp)| bp.enabled) {
+            let addr = breakpoint.addr.wrapping_add(inferior.load_bias());
+            if let Err(err) = inferior.set_breakpoint(addr) {
+                println!("Cannot insert breakpoint {} at {:#x}: {}", num, addr, err);
+            }
+        }
+        let stale: Vec<usize> = self
+            .watchpoints
+            .iter()
+            .filter(|(_, watchpoint)| !watchpoint.global)
+            .map(|(num, _)| *num)
+            .collect();
+        for num in stale {
+            self.watchpoints.remove(&num);
+            println!(
+                "Watchpoint {} deleted because it doesn't watch a global variable.",
+                num
+            );
+        }
+        let mut failed = Vec::new();
+        for (num, watchpoint) in self.watchpoints.iter_mut() {
+            let addr = watchpoint.addr.wrapping_add(inferior.load_bias());
+            let size = watchpoint.entity_type.size;
+            match inferior
+                .set_watchpoint(watchpoint.slot, addr, size)
+                .and_then(|()| inferior.read_bytes(addr, size))
+            {
+                Ok(value) => watchpoint.old_value = value,
+                Err(err) => {
+                    println!(
+                        "Watchpoint {} deleted because {:#x} cannot be watched: {}",
+                        num, addr, err
+                    );
+                    failed.push((*num, watchpoint.slot));
+                }
+            }
+        }
+        for (num, slot) in failed {
+            self.watchpoints.remove(&num);
+            if let Err(err) = inferior.clear_watchpoint(slot) {
+                println!("Could not clear watchpoint {}: {}", num, err);
+            }
+        }
+    }
+
     /// This function prompts the user to enter a command, and continues re-prompting until the user
     /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
     ///
@@ -170,37 +735,1565 @@ impl Debugger {
     }
 
     fn inferior_continue_exec(&mut self) {
-        if let Some(inferior) = &mut self.inferior {
-            match inferior.continue_exec() {
-                Ok(status) => match status {
-                    Status::Stopped(signal, rip) => {
-                        let line = self.debug_data.get_line_from_addr(rip).unwrap();
-                        let function = self.debug_data.get_function_from_addr(rip).unwrap();
-                        println!("Child stopped (signal {})", signal);
-                        println!("Stopped at {}:{}", line.file, line.number);
-                        println!("In function `{}'", function);
-                    }
-                    Status::Exited(status) => {
-                        self.inferior = None;
-                        println!("Child exited (signal {})", status);
-                    }
-                    Status::Signaled(signal) => {
-                        self.inferior = None;
-                        println!("Child signaled (signal {})", signal);
+        self.resume_inferior(|inferior, _, filter| inferior.continue_exec(filter));
+    }
+
+    /// Runs until the current function returns, then prints its return value.
+    fn finish(&mut self) {
+        let inferior = self.inferior.as_ref().unwrap();
+        let function = match inferior.get_pc() {
+            Ok(rip) => self
+                .debug_data
+                .get_function_containing(rip.wrapping_sub(inferior.load_bias()))
+                .cloned(),
+            Err(err) => {
+                println!("Could not read registers: {}", err);
+                return;
+            }
+        };
+        let (return_addr, caller_sp) = match inferior.return_address(&self.debug_data) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => {
+                println!("\"finish\" not meaningful in the outermost frame.");
+                return;
+            }
+            Err(err) => {
+                println!("Could not find the return address: {}", err);
+                return;
+            }
+        };
+        if let Some(func) = &function {
+            println!("Run till exit from `{}'", func.name);
+        }
+
+        let status = self.resume_inferior(|inferior, _, filter| {
+            inferior.run_until_return(return_addr, caller_sp, filter)
+        });
+        if let Some(Status::Stopped(_, rip)) = status {
+            if rip != return_addr {
+                return;
+            }
+            if let Some(return_type) = function.and_then(|func| func.return_type) {
+                let resolved = self.debug_data.resolve_type(&return_type);
+                match self.inferior.as_ref().unwrap().read_return_value(resolved) {
+                    Ok(bytes) => println!(
+                        "Value returned: {}",
+                        self.debug_data.format_value(&return_type, &bytes)
+                    ),
+                    Err(err) => println!("Could not read the return value: {}", err),
+                }
+            }
+        }
+    }
+
+    /// Resumes the inferior using `resume` (e.g. continuing or stepping) and reports the state it
+    /// ends up in. Returns that state, or None if the inferior could not be resumed.
+    ///
+    /// `resume` is given a filter (see `filter_stop`) so that it carries on past breakpoints
+    /// whose condition does not hold or which are being ignored. If the inferior execs another
+    /// program, that program is loaded and the inferior continued.
+    fn resume_inferior<F>(&mut self, mut resume: F) -> Option<Status>
+    where
+        F: FnMut(&mut Inferior, &DwarfData, &mut StopFilter) -> Result<Status, nix::Error>,
+    {
+        let mut after_exec = false;
+        let status = loop {
+            let mut inferior = match self.inferior.take() {
+                Some(inferior) => inferior,
+                None => {
+                    println!("There is no inferior.");
+                    return None;
+                }
+            };
+            let debug_data = Rc::clone(&self.debug_data);
+            let mut filter = |inferior: &Inferior, status| self.filter_stop(inferior, status);
+            let result = if after_exec {
+                inferior.continue_exec(&mut filter)
+            } else {
+                resume(&mut inferior, &debug_data, &mut filter)
+            };
+            self.inferior = Some(inferior);
+            match result {
+                Ok(Status::Exec) => {
+                    self.follow_exec();
+                    after_exec = true;
+                }
+                Ok(status) => break status,
+                Err(err) => {
+                    println!("Inferior cannot be executed: {}", err);
+                    return None;
+                }
+            }
+        };
+        if let Status::Stopped(Signal::SIGTRAP, rip) = status {
+            // finish stops at its return address without consulting the filter, and that may be
+            // where a watched frame returns to as well
+            let inferior = self.inferior.take().unwrap();
+            self.check_watchpoint_scopes(&inferior, rip);
+            self.inferior = Some(inferior);
+        }
+        self.disarm_expired_watchpoints();
+        self.report_stop(status);
+        Some(status)
+    }
+
+    /// Executes `count` machine instructions, stepping into calls if `step_into` is set and
+    /// running them to completion otherwise, then prints the instruction the inferior stopped at.
+    /// Stepping ends early at a breakpoint or watchpoint that triggers along the way.
+    fn step_instructions(&mut self, count: usize, step_into: bool) {
+        let mut status = None;
+        let mut remaining = count;
+        while remaining > 0 {
+            let mut inferior = self.inferior.take().unwrap();
+            // Whether a stop within a call being stepped over was reported
+            let mut reported = false;
+            let result = if step_into {
+                inferior.step_instruction()
+            } else {
+                inferior.step_over_instruction(&mut |inferior, status| {
+                    let stop = self.filter_stop(inferior, status);
+                    reported |= stop == Some(true);
+                    stop
+                })
+            };
+            self.inferior = Some(inferior);
+            match result {
+                Ok(other) if reported => {
+                    status = Some(other);
+                    break;
+                }
+                Ok(Status::Stopped(Signal::SIGTRAP, rip)) => {
+                    status = Some(Status::Stopped(Signal::SIGTRAP, rip));
+                    remaining -= 1;
+                    let inferior = self.inferior.take().unwrap();
+                    let stop = self.check_stop(&inferior, rip);
+                    self.inferior = Some(inferior);
+                    if stop == Some(true) {
+                        break;
                     }
-                },
+                }
+                // The signal arrived before the instruction could execute, so try again
+                Ok(Status::Stopped(signal, _)) if !self.stops_on_signal(signal) => {}
+                Ok(other) => {
+                    status = Some(other);
+                    break;
+                }
                 Err(err) => {
                     println!("Inferior cannot be executed: {}", err);
+                    return;
+                }
+            }
+        }
+        self.disarm_expired_watchpoints();
+        let status = match status {
+            Some(status) => status,
+            None => return,
+        };
+        self.report_stop(status);
+        if let Status::Stopped(_, rip) = status {
+            if let Err(err) = self.examine(
+                ExamineFormat {
+                    count: 1,
+                    format: 'i',
+                    size: 1,
+                },
+                &format!("*{:#x}", rip),
+            ) {
+                println!("{}", err);
+            }
+        }
+    }
+
+    /// Resets the frame selection after the inferior ran and prints the state it ended up in.
+    fn report_stop(&mut self, status: Status) {
+        self.selected_frame = 0;
+        self.list_position = None;
+        match status {
+            Status::Stopped(signal, rip) => {
+                println!("Child stopped (signal {})", signal);
+                self.print_location(rip);
+                match signal {
+                    Signal::SIGSEGV | Signal::SIGBUS | Signal::SIGFPE | Signal::SIGILL => {
+                        self.report_fault(signal)
+                    }
+                    _ => {}
+                }
+            }
+            Status::Exited(status) => {
+                self.inferior = None;
+                println!("Child exited (signal {})", status);
+            }
+            Status::Signaled(signal) => {
+                self.inferior = None;
+                println!("Child signaled (signal {})", signal);
+            }
+            Status::Exec => {
+                self.follow_exec();
+                let rip = self.inferior.as_ref().unwrap().get_pc();
+                if let Ok(rip) = rip {
+                    self.print_location(rip);
+                }
+            }
+        }
+    }
+
+    /// Picks up the program the inferior exec'd: loads its debugging symbols and inserts the
+    /// breakpoints (those that survive the switch) into it.
+    fn follow_exec(&mut self) {
+        let mut inferior = self.inferior.take().unwrap();
+        let path = match fs::read_link(format!("/proc/{}/exe", inferior.pid())) {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(err) => {
+                println!("Could not find the program the inferior executed: {}", err);
+                self.inferior = Some(inferior);
+                return;
+            }
+        };
+        println!(
+            "process {} is executing new program: {}",
+            inferior.pid(),
+            path
+        );
+        if self.load_target(&path) {
+            self.install_breakpoints(&mut inferior);
+        }
+        self.inferior = Some(inferior);
+    }
+
+    /// Prints the source location the inferior is stopped at.
+    fn print_location(&self, rip: usize) {
+        let pc = rip.wrapping_sub(self.inferior.as_ref().unwrap().load_bias());
+        match self.debug_data.get_line_from_addr(pc) {
+            Some(line) => println!("Stopped at {}:{}", line.file, line.number),
+            None => println!("Stopped at {:#x}", rip),
+        }
+        if let Some(function) = self.debug_data.get_function_from_addr(pc) {
+            println!("In function `{}'", function);
+        }
+    }
+
+    /// Explains a fault the inferior stopped at: what went wrong and, for bad memory accesses,
+    /// where the address lies in the address space. The offending source line and a backtrace
+    /// follow. If the fault is in code without line information (such as a library function
+    /// passed a bad pointer), the line shown is that of the innermost frame which has some.
+    fn report_fault(&self, signal: Signal) {
+        let info = match self.inferior.as_ref().unwrap().signal_info() {
+            Ok(info) => info,
+            Err(err) => {
+                println!("Could not get the signal details: {}", err);
+                return;
+            }
+        };
+        if info.code <= 0 {
+            println!("The signal was sent by a process rather than raised by a fault");
+            return;
+        }
+        let memory_fault = signal == Signal::SIGSEGV || signal == Signal::SIGBUS;
+        match fault_code_name(signal, info.code) {
+            Some((name, meaning)) if memory_fault => {
+                println!(
+                    "Invalid memory access at {:#x} ({}: {})",
+                    info.addr, name, meaning
+                );
+                self.print_mapping(info.addr);
+            }
+            Some((name, meaning)) => {
+                println!(
+                    "Fault at instruction {:#x} ({}: {})",
+                    info.addr, name, meaning
+                )
+            }
+            None if info.code == libc::SI_KERNEL => {
+                println!("General protection fault (the faulting address is unknown)")
+            }
+            None => println!("Fault at {:#x} (si_code {})", info.addr, info.code),
+        }
+
+        let frames = match self.inferior.as_ref().unwrap().unwind(&self.debug_data) {
+            Ok(frames) => frames,
+            Err(err) => {
+                println!("Could not unwind the stack: {}", err);
+                return;
+            }
+        };
+        let load_bias = self.load_bias();
+        let source = frames.iter().find_map(|frame| {
+            let pc = frame.lookup_pc().wrapping_sub(load_bias);
+            match self.debug_data.get_line_from_addr(pc) {
+                Some(line) if line.number != 0 => Some((frame, line)),
+                _ => None,
+            }
+        });
+        if let Some((frame, line)) = source {
+            if frame.level > 0 {
+                self.print_frame(frame);
+            }
+            self.print_source_line(&line);
+        }
+        frames.iter().for_each(|frame| self.print_frame(frame));
+    }
+
+    /// Prints where an address lies in the inferior's memory map: the mapping containing it or,
+    /// if it isn't mapped, the mappings on either side of it.
+    fn print_mapping(&self, addr: usize) {
+        let maps = match self.inferior.as_ref().unwrap().memory_maps() {
+            Ok(maps) => maps,
+            Err(err) => {
+                println!("Could not read the memory map: {}", err);
+                return;
+            }
+        };
+        let describe = |mapping: &Mapping| {
+            let path = if mapping.path.is_empty() {
+                "(anonymous)"
+            } else {
+                &mapping.path
+            };
+            format!(
+                "{:#x}-{:#x} {} {}",
+                mapping.start, mapping.end, mapping.perms, path
+            )
+        };
+        if let Some(mapping) = maps
+            .iter()
+            .find(|mapping| mapping.start <= addr && addr < mapping.end)
+        {
+            println!(
+                "Address {:#x} is at offset {:#x} in mapping {}",
+                addr,
+                addr - mapping.start,
+                describe(mapping)
+            );
+            return;
+        }
+        if addr < NULL_PAGE_SIZE {
+            println!(
+                "Address {:#x} is not mapped (looks like a NULL pointer dereference)",
+                addr
+            );
+        } else {
+            println!("Address {:#x} is not mapped", addr);
+        }
+        if let Some(below) = maps.iter().rev().find(|mapping| mapping.end <= addr) {
+            println!("  {:#x} bytes past {}", addr - below.end, describe(below));
+        }
+        if let Some(above) = maps.iter().find(|mapping| mapping.start > addr) {
+            println!(
+                "  {:#x} bytes before {}",
+                above.start - addr,
+                describe(above)
+            );
+        }
+    }
+
+    /// Prints a single line of source, formatted as `list` does.
+    fn print_source_line(&self, line: &Line) {
+        let path = self.debug_data.get_source_path(&line.file);
+        // Line 0 means the code isn't attributed to any line of the source
+        let index = match line.number.checked_sub(1) {
+            Some(index) => index,
+            None => return,
+        };
+        if let Some(text) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|source| source.lines().nth(index).map(str::to_string))
+        {
+            println!("=> {:<4} {}", line.number, text);
+        }
+    }
+
+    /// Prints the call stack of the current thread, innermost frame first.
+    fn print_backtrace(&self) {
+        match self.inferior.as_ref().unwrap().unwind(&self.debug_data) {
+            Ok(frames) => frames.iter().for_each(|frame| self.print_frame(frame)),
+            Err(err) => println!("Could not unwind the stack: {}", err),
+        }
+    }
+
+    /// Prints a one-line summary of a frame, as in a backtrace.
+    fn print_frame(&self, frame: &Frame) {
+        println!("#{} {}", frame.level, self.describe_frame(frame));
+    }
+
+    /// Returns the function a frame is executing, and where in the source it is.
+    fn describe_frame(&self, frame: &Frame) -> String {
+        let pc = frame
+            .lookup_pc()
+            .wrapping_sub(self.inferior.as_ref().unwrap().load_bias());
+        let function = self
+            .debug_data
+            .get_function_from_addr(pc)
+            .unwrap_or_else(|| "??".to_string());
+        match self.debug_data.get_line_from_addr(pc) {
+            Some(line) => format!("{} ({}:{})", function, line.file, line.number),
+            None => format!("{} ({:#x})", function, frame.pc),
+        }
+    }
+
+    /// Prints the threads of the inferior along with the innermost frame of each, marking the
+    /// current thread.
+    fn print_threads(&mut self) {
+        let inferior = self.inferior.as_mut().unwrap();
+        let current = inferior.current_thread();
+        let threads: Vec<(usize, Pid)> = inferior
+            .threads()
+            .iter()
+            .map(|(&num, &tid)| (num, tid))
+            .collect();
+        println!("  Id   Target Id          Frame");
+        for (num, tid) in threads {
+            let inferior = self.inferior.as_mut().unwrap();
+            inferior.select_thread(num);
+            let frame = match inferior.current_frame(&self.debug_data) {
+                Ok(frame) => self.describe_frame(&frame),
+                Err(err) => format!("<unavailable: {}>", err),
+            };
+            let marker = if num == current { '*' } else { ' ' };
+            println!(
+                "{} {:<4} {:<18} {}",
+                marker,
+                num,
+                format!("LWP {}", tid),
+                frame
+            );
+        }
+        self.inferior.as_mut().unwrap().select_thread(current);
+    }
+
+    /// Returns the currently selected frame of the (stopped) inferior.
+    fn selected_frame(&self) -> Result<Frame, String> {
+        let inferior = self.inferior.as_ref().unwrap();
+        let mut frames = inferior
+            .unwind(&self.debug_data)
+            .map_err(|err| format!("Could not unwind the stack: {}", err))?;
+        if self.selected_frame >= frames.len() {
+            return Err(format!("No frame at level {}.", self.selected_frame));
+        }
+        Ok(frames.swap_remove(self.selected_frame))
+    }
+
+    /// Selects the frame at the given level and prints it.
+    fn select_frame(&mut self, level: usize) {
+        match self.inferior.as_ref().unwrap().unwind(&self.debug_data) {
+            Ok(frames) => match frames.get(level) {
+                Some(frame) => {
+                    self.selected_frame = level;
+                    self.list_position = None;
+                    self.print_frame(frame);
+                }
+                None => println!("No frame at level {}.", level),
+            },
+            Err(err) => println!("Could not unwind the stack: {}", err),
+        }
+    }
+
+    /// Selects the frame `count` levels further out than the selected one, stopping at the
+    /// outermost frame.
+    fn frame_up(&mut self, count: usize) {
+        let depth = match self.inferior.as_ref().unwrap().unwind(&self.debug_data) {
+            Ok(frames) => frames.len(),
+            Err(err) => {
+                println!("Could not unwind the stack: {}", err);
+                return;
+            }
+        };
+        if self.selected_frame + 1 >= depth {
+            println!("Initial frame selected; you cannot go up.");
+        } else {
+            self.select_frame((self.selected_frame + count).min(depth - 1));
+        }
+    }
+
+    /// Decides what becomes of a stop of `inferior` (which has been taken out of `self.inferior`
+    /// while it runs), as a `StopFilter`: stops at a breakpoint or watchpoint are reported if it
+    /// triggers, and signals if they are set to stop the program.
+    fn filter_stop(&mut self, inferior: &Inferior, status: Status) -> Option<bool> {
+        match status {
+            Status::Stopped(Signal::SIGTRAP, rip) => self.check_stop(inferior, rip),
+            Status::Stopped(signal, _) => Some(self.stops_on_signal(signal)),
+            _ => None,
+        }
+    }
+
+    /// Checks whether a stop at `rip` was caused by a breakpoint or watchpoint, and if so, returns
+    /// whether it triggers, updating the hit and ignore counts of the breakpoints there. Stops
+    /// caused by a watchpoint trigger only if the value changed, and stops where a watched frame
+    /// returns always trigger.
+    fn check_stop(&mut self, inferior: &Inferior, rip: usize) -> Option<bool> {
+        let watchpoint_stop = self.check_watchpoints(inferior);
+        let left_scope = self.check_watchpoint_scopes(inferior, rip);
+        if watchpoint_stop == Some(true) {
+            return watchpoint_stop;
+        }
+
+        let pc = rip.wrapping_sub(inferior.load_bias());
+        let debug_data = &self.debug_data;
+        // Only unwound for conditions, as this is checked after every instruction when stepping
+        let mut context = None;
+
+        let mut at_breakpoint = false;
+        let mut stop = false;
+        for (num, breakpoint) in self.breakpoints.iter_mut() {
+            if !breakpoint.enabled || breakpoint.addr != pc {
+                continue;
+            }
+            at_breakpoint = true;
+            if let Some(condition) = &breakpoint.condition {
+                let context = context.get_or_insert_with(|| {
+                    inferior
+                        .current_frame(debug_data)
+                        .map(|frame| FrameContext {
+                            inferior,
+                            debug_data,
+                            frame,
+                        })
+                        .map_err(|err| format!("Could not unwind the stack: {}", err))
+                });
+                match context
+                    .as_ref()
+                    .map_err(String::clone)
+                    .and_then(|context| condition.is_true(context))
+                {
+                    Ok(false) => continue,
+                    Ok(true) => {}
+                    Err(err) => {
+                        println!("Error in testing condition for breakpoint {}: {}", num, err)
+                    }
+                }
+            }
+            breakpoint.hit_count += 1;
+            if breakpoint.ignore_count > 0 {
+                breakpoint.ignore_count -= 1;
+                continue;
+            }
+            println!("Hit breakpoint {}", num);
+            stop = true;
+        }
+        let at_scope_breakpoint = self
+            .watchpoints
+            .values()
+            .any(|wp| wp.scope.is_some_and(|scope| scope.return_addr == pc));
+        if left_scope {
+            Some(true)
+        } else if at_breakpoint || at_scope_breakpoint {
+            Some(stop)
+        } else {
+            watchpoint_stop
+        }
+    }
+
+    /// Deletes the watchpoints whose frame has just returned, which is when its thread stops at
+    /// the frame's return address with the stack pointer at its CFA (rather than below it, in a
+    /// recursive call of the same function). Returns whether there were any.
+    fn check_watchpoint_scopes(&mut self, inferior: &Inferior, rip: usize) -> bool {
+        let pc = rip.wrapping_sub(inferior.load_bias());
+        let thread = inferior.current_thread();
+        let sp = match inferior.get_sp() {
+            Ok(sp) => sp,
+            Err(_) => return false,
+        };
+        let expired: Vec<usize> = self
+            .watchpoints
+            .iter()
+            .filter(|(_, wp)| {
+                wp.scope.is_some_and(|scope| {
+                    scope.return_addr == pc && scope.thread == thread && sp >= scope.cfa
+                })
+            })
+            .map(|(num, _)| *num)
+            .collect();
+        for num in &expired {
+            println!(
+                "Watchpoint {} deleted because the program has left the block in which its \
+                 expression is valid.",
+                num
+            );
+            let watchpoint = self.watchpoints.remove(num).unwrap();
+            self.expired_watchpoints.push(watchpoint);
+        }
+        !expired.is_empty()
+    }
+
+    /// Disarms the watchpoints deleted by `check_watchpoint_scopes`, which can only be done once
+    /// the inferior is back in `self.inferior`.
+    fn disarm_expired_watchpoints(&mut self) {
+        for watchpoint in std::mem::take(&mut self.expired_watchpoints) {
+            if let Some(inferior) = &mut self.inferior {
+                if let Err(err) = inferior.clear_watchpoint(watchpoint.slot) {
+                    println!("Could not clear watchpoint {}: {}", watchpoint.name, err);
+                }
+            }
+            if let Some(scope) = watchpoint.scope {
+                self.sync_breakpoint(scope.return_addr);
+            }
+        }
+    }
+
+    /// Decides whether a signal the inferior received should be reported as a stop, mentioning
+    /// it if it shouldn't but is to be printed.
+    fn stops_on_signal(&self, signal: Signal) -> bool {
+        let policy = self.signal_policies[&signal];
+        if !policy.stop && policy.print {
+            println!("Child received signal {}", signal);
+        }
+        policy.stop
+    }
+
+    /// Changes what happens when the inferior receives a signal, then prints the new policy.
+    fn handle_signal(&mut self, signal: Signal, actions: &[String]) {
+        let policy = self.signal_policies.get_mut(&signal).unwrap();
+        for action in actions {
+            match action.as_str() {
+                // A signal that stops the inferior is always mentioned, and the other way around
+                "stop" => {
+                    policy.stop = true;
+                    policy.print = true;
+                }
+                "nostop" => policy.stop = false,
+                "print" => policy.print = true,
+                "noprint" => {
+                    policy.print = false;
+                    policy.stop = false;
+                }
+                "pass" => policy.pass = true,
+                "nopass" => policy.pass = false,
+                _ => {}
+            }
+        }
+        let pass = policy.pass;
+        if let Some(inferior) = &mut self.inferior {
+            inferior.set_signal_pass(signal, pass);
+        }
+        self.print_signal_policies(Some(signal));
+    }
+
+    /// Prints how the given signal, or every signal, is handled.
+    fn print_signal_policies(&self, signal: Option<Signal>) {
+        let yes_no = |value: bool| if value { "Yes" } else { "No" };
+        println!("Signal        Stop\tPrint\tPass to program");
+        for signal in Signal::iterator().filter(|&sig| signal.is_none_or(|signal| sig == signal)) {
+            let policy = self.signal_policies[&signal];
+            println!(
+                "{:<14}{}\t{}\t{}",
+                signal.as_str(),
+                yes_no(policy.stop),
+                yes_no(policy.print),
+                yes_no(policy.pass)
+            );
+        }
+    }
+
+    /// Checks whether the inferior stopped because of a watchpoint. If so, reports the old and new
+    /// values and returns whether the watched value actually changed.
+    fn check_watchpoints(&mut self, inferior: &Inferior) -> Option<bool> {
+        let slot = inferior.take_triggered_watchpoint().ok()??;
+        let (num, watchpoint) = self
+            .watchpoints
+            .iter_mut()
+            .find(|(_, watchpoint)| watchpoint.slot == slot)?;
+        let addr = watchpoint.addr.wrapping_add(inferior.load_bias());
+        let new_value = match inferior.read_bytes(addr, watchpoint.entity_type.size) {
+            Ok(value) => value,
+            Err(err) => {
+                println!("Cannot access memory for watchpoint {}: {}", num, err);
+                return Some(true);
+            }
+        };
+        if new_value == watchpoint.old_value {
+            return Some(false);
+        }
+        watchpoint.hit_count += 1;
+        println!("Hit watchpoint {}: {}", num, watchpoint.name);
+        println!(
+            "Old value = {}",
+            self.debug_data
+                .format_value(&watchpoint.entity_type, &watchpoint.old_value)
+        );
+        println!(
+            "New value = {}",
+            self.debug_data
+                .format_value(&watchpoint.entity_type, &new_value)
+        );
+        watchpoint.old_value = new_value;
+        Some(true)
+    }
+
+    fn set_watchpoint(&mut self, input: &str) {
+        let expr = match Expression::parse(input) {
+            Ok(expr) => expr,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let context = match self.selected_context() {
+            Ok(context) => context,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let value = match expr.evaluate(&context) {
+            Ok(value) => value,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let addr = match value.address {
+            Some(addr) => addr,
+            None => {
+                println!("Cannot watch constant value `{}'.", input);
+                return;
+            }
+        };
+        // Only a global variable (or a member of one) is still in the same place when the inferior
+        // restarts; anything reached through a pointer may not even be mapped yet
+        let pc = context
+            .frame
+            .lookup_pc()
+            .wrapping_sub(context.inferior.load_bias());
+        let mut lvalue = &expr;
+        while let Expression::Member(operand, _) = lvalue {
+            lvalue = operand;
+        }
+        let global = match lvalue {
+            Expression::Variable(name) => matches!(
+                self.debug_data
+                    .get_variable(pc, name)
+                    .map(|var| &var.location),
+                Some(Location::Address(_))
+            ),
+            _ => false,
+        };
+        // Watching a local only makes sense for as long as its frame exists
+        let local = expr.variables().iter().any(|name| {
+            self.debug_data
+                .get_variable(pc, name)
+                .is_some_and(|var| !matches!(var.location, Location::Address(_)))
+        });
+        let scope = if local {
+            match context.inferior.unwind(&self.debug_data) {
+                Ok(frames) => frames
+                    .get(context.frame.level + 1)
+                    .map(|caller| WatchScope {
+                        thread: context.inferior.current_thread(),
+                        cfa: context.frame.cfa,
+                        return_addr: caller.pc.wrapping_sub(context.inferior.load_bias()),
+                    }),
+                Err(err) => {
+                    println!("Could not unwind the stack: {}", err);
+                    return;
                 }
             }
         } else {
-            println!("There is no inferior.");
+            None
+        };
+        let watchpoints = &self.watchpoints;
+        let slot = match (0..4).find(|slot| watchpoints.values().all(|wp| wp.slot != *slot)) {
+            Some(slot) => slot,
+            None => {
+                println!("All 4 hardware watchpoints are in use.");
+                return;
+            }
+        };
+        let size = value.value_type.size;
+        let inferior = self.inferior.as_mut().unwrap();
+        if let Err(err) = inferior.set_watchpoint(slot, addr, size) {
+            println!(
+                "Cannot watch {} (hardware watchpoints need an aligned 1, 2, 4 or 8 byte value): \
+                 {}",
+                input, err
+            );
+            return;
+        }
+        println!("Hardware watchpoint {}: {}", self.next_breakpoint, input);
+        self.watchpoints.insert(
+            self.next_breakpoint,
+            Watchpoint {
+                name: input.to_string(),
+                addr: addr.wrapping_sub(inferior.load_bias()),
+                entity_type: value.value_type,
+                slot,
+                global,
+                scope,
+                old_value: value.bytes,
+                hit_count: 0,
+            },
+        );
+        self.next_breakpoint += 1;
+        if let Some(scope) = scope {
+            self.sync_breakpoint(scope.return_addr);
+        }
+    }
+
+    /// Installs or removes the breakpoint instruction at `addr` in the running inferior, depending
+    /// on whether any enabled breakpoint is still set there, or a watched frame returns there.
+    fn sync_breakpoint(&mut self, addr: usize) {
+        if let Some(inferior) = &mut self.inferior {
+            let wanted = self
+                .breakpoints
+                .values()
+                .any(|bp| bp.enabled && bp.addr == addr)
+                || self
+                    .watchpoints
+                    .values()
+                    .any(|wp| wp.scope.is_some_and(|scope| scope.return_addr == addr));
+            let runtime_addr = addr.wrapping_add(inferior.load_bias());
+            let result = if wanted {
+                inferior.set_breakpoint(runtime_addr)
+            } else {
+                inferior.remove_breakpoint(runtime_addr)
+            };
+            if let Err(err) = result {
+                println!(
+                    "Could not update breakpoint at {:#x}: {}",
+                    runtime_addr, err
+                );
+            }
+        }
+    }
+
+    fn set_breakpoint_enabled(&mut self, num: usize, enabled: bool) {
+        match self.breakpoints.get_mut(&num) {
+            Some(breakpoint) => {
+                breakpoint.enabled = enabled;
+                let addr = breakpoint.addr;
+                self.sync_breakpoint(addr);
+            }
+            None => println!("No breakpoint number {}.", num),
         }
     }
 
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty() && self.watchpoints.is_empty() {
+            println!("No breakpoints or watchpoints.");
+            return;
+        }
+        println!("Num  Enb  Address             Hits  What");
+        for (num, breakpoint) in &self.breakpoints {
+            let location = match self.debug_data.get_line_from_addr(breakpoint.addr) {
+                Some(line) => format!("{}", line),
+                None => String::new(),
+            };
+            println!(
+                "{:<4} {:<4} {:<#18x}  {:<5} {}",
+                num,
+                if breakpoint.enabled { "y" } else { "n" },
+                breakpoint.addr.wrapping_add(self.load_bias()),
+                breakpoint.hit_count,
+                location
+            );
+            if let Some(condition) = &breakpoint.condition {
+                println!("        stop only if {}", condition);
+            }
+            if breakpoint.ignore_count > 0 {
+                println!("        ignore next {} hits", breakpoint.ignore_count);
+            }
+        }
+        for (num, watchpoint) in &self.watchpoints {
+            println!(
+                "{:<4} {:<4} {:<#18x}  {:<5} watch {}",
+                num,
+                "y",
+                watchpoint.addr.wrapping_add(self.load_bias()),
+                watchpoint.hit_count,
+                watchpoint.name
+            );
+        }
+    }
+
+    /// Returns how far the running inferior was relocated, or 0 if nothing is running.
+    fn load_bias(&self) -> usize {
+        self.inferior.as_ref().map_or(0, Inferior::load_bias)
+    }
+
+    /// Returns a context for evaluating expressions in the selected frame.
+    fn selected_context(&self) -> Result<FrameContext<'_>, String> {
+        Ok(FrameContext {
+            inferior: self.inferior.as_ref().unwrap(),
+            debug_data: &self.debug_data,
+            frame: self.selected_frame()?,
+        })
+    }
+
+    fn print_expression(&self, input: &str) {
+        let expr = match Expression::parse(input) {
+            Ok(expr) => expr,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let context = match self.selected_context() {
+            Ok(context) => context,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        // Plain variables get the same treatment as in `info locals`, including <optimized out>
+        if let Expression::Variable(name) = &expr {
+            let pc = context
+                .frame
+                .lookup_pc()
+                .wrapping_sub(context.inferior.load_bias());
+            if let Some(var) = self.debug_data.get_variable(pc, name) {
+                let value = context
+                    .inferior
+                    .read_variable(var, &context.frame, &self.debug_data);
+                self.print_value(var, value);
+                return;
+            }
+        }
+        match expr.evaluate(&context) {
+            Ok(value) => println!(
+                "{} = {}",
+                input,
+                self.debug_data
+                    .format_value(&value.value_type, &value.bytes)
+            ),
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    /// Assigns the value of the expression `value` to the variable, register or other lvalue
+    /// described by `target`, converting it to the target's type.
+    fn set_variable(&mut self, target: &str, value: &str) -> Result<(), String> {
+        let target = Expression::parse(target)?;
+        let value = Expression::parse(value)?;
+        let context = self.selected_context()?;
+        let value = value.evaluate(&context)?;
+        let frame = context.frame.clone();
+        let pc = frame.lookup_pc().wrapping_sub(context.inferior.load_bias());
+
+        if let Expression::Register(name) = &target {
+            if frame.level != 0 {
+                return Err("Registers can only be changed in the innermost frame.".to_string());
+            }
+            let long = Type::new("long".to_string(), 8, TypeKind::Base(Encoding::Signed));
+            let value = expression::cast(value, long, None, &self.debug_data)?;
+            let value = u64::from_le_bytes(value.bytes[..8].try_into().unwrap());
+            return self
+                .inferior
+                .as_ref()
+                .unwrap()
+                .set_register(name, value)
+                .map_err(|err| err.to_string());
+        }
+        // Variables may live in registers, so they are written wherever they are
+        if let Expression::Variable(name) = &target {
+            if let Some(var) = self.debug_data.get_variable(pc, name) {
+                let value = expression::cast(
+                    value,
+                    var.entity_type.clone(),
+                    Some(var.type_id),
+                    &self.debug_data,
+                )?;
+                return self
+                    .inferior
+                    .as_mut()
+                    .unwrap()
+                    .write_variable(var, &frame, &self.debug_data, &value.bytes)
+                    .map_err(|err| format!("Cannot write {}: {}", name, err));
+            }
+        }
+        if let Some((mut holder, member)) = target.bit_field(&context)? {
+            let addr = holder
+                .address
+                .ok_or("Left operand of assignment is not an lvalue.")?;
+            let member_type = self
+                .debug_data
+                .get_type(member.type_id)
+                .ok_or("Bit-field has an unknown type.")?;
+            let value = expression::cast(
+                value,
+                member_type.clone(),
+                Some(member.type_id),
+                &self.debug_data,
+            )?;
+            let changed = self
+                .debug_data
+                .store_member(&member, &mut holder.bytes, &value.bytes)
+                .ok_or("Bit-field is out of bounds.")?;
+            let addr = addr + changed.start;
+            return self
+                .inferior
+                .as_mut()
+                .unwrap()
+                .write_bytes(addr, &holder.bytes[changed])
+                .map_err(|err| format!("Cannot access memory at address {:#x}: {}", addr, err));
+        }
+        let target = target.evaluate(&context)?;
+        let addr = target
+            .address
+            .ok_or("Left operand of assignment is not an lvalue.")?;
+        let value = expression::cast(value, target.value_type, target.type_id, &self.debug_data)?;
+        self.inferior
+            .as_mut()
+            .unwrap()
+            .write_bytes(addr, &value.bytes)
+            .map_err(|err| format!("Cannot access memory at address {:#x}: {}", addr, err))
+    }
+
+    /// Prints a variable's value as read by `Inferior::read_variable`, or why it couldn't be read.
+    fn print_value(&self, var: &Variable, value: Result<Vec<u8>, ReadError>) {
+        match value {
+            Ok(bytes) => println!(
+                "{} = {}",
+                var.name,
+                self.debug_data.format_value(&var.entity_type, &bytes)
+            ),
+            Err(ReadError::OptimizedOut) => println!("{} = <optimized out>", var.name),
+            Err(ReadError::Ptrace(err)) => {
+                println!("Cannot access memory for {}: {}", var.name, err)
+            }
+            Err(err) => println!("Cannot read {}: {}", var.name, err),
+        }
+    }
+
+    /// Prints the value of every local variable (and parameter) of the selected frame.
+    fn print_locals(&self) {
+        let frame = match self.selected_frame() {
+            Ok(frame) => frame,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let inferior = self.inferior.as_ref().unwrap();
+        let pc = frame.lookup_pc().wrapping_sub(inferior.load_bias());
+        let function = match self.debug_data.get_function_containing(pc) {
+            Some(function) => function,
+            None => {
+                println!("No symbol table info available.");
+                return;
+            }
+        };
+        if function.variables.is_empty() {
+            println!("No locals.");
+        }
+        for var in &function.variables {
+            self.print_value(var, inferior.read_variable(var, &frame, &self.debug_data));
+        }
+    }
+
+    /// Prints the registers of the selected frame (or just the named ones), gdb style: the raw
+    /// value in hex, then in its natural form.
+    fn print_registers(&self, names: &[String]) {
+        let frame = match self.selected_frame() {
+            Ok(frame) => frame,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let inferior = self.inferior.as_ref().unwrap();
+        let names: Vec<&str> = if names.is_empty() {
+            REGISTER_NAMES.to_vec()
+        } else {
+            names.iter().map(String::as_str).collect()
+        };
+        for name in names {
+            let value = match inferior.register_value(&frame, name) {
+                Ok(value) => value,
+                Err(ReadError::OptimizedOut) => {
+                    println!("{:<15}<not saved>", name);
+                    continue;
+                }
+                Err(err) => {
+                    println!("{}", err);
+                    continue;
+                }
+            };
+            let natural = match name {
+                "rip" | "pc" => format!("{:#x}{}", value, self.describe_code_address(value)),
+                "rsp" | "rbp" | "sp" | "fp" | "fs_base" | "gs_base" => format!("{:#x}", value),
+                "eflags" => {
+                    let flags: Vec<&str> = EFLAGS
+                        .iter()
+                        .filter(|(bit, _)| value & (1 << bit) != 0)
+                        .map(|(_, flag)| *flag)
+                        .collect();
+                    format!("[ {} ]", flags.join(" "))
+                }
+                _ => (value as i64).to_string(),
+            };
+            println!("{:<15}{:<19}{}", name, format!("{:#x}", value), natural);
+        }
+    }
+
+    /// Describes the function a (runtime) code address belongs to, as in ` <main+4>`, or returns
+    /// an empty string if it isn't in a function we know.
+    fn describe_code_address(&self, addr: u64) -> String {
+        let load_bias = self
+            .inferior
+            .as_ref()
+            .map_or(0, |inferior| inferior.load_bias());
+        let addr = (addr as usize).wrapping_sub(load_bias);
+        match self.debug_data.get_function_containing(addr) {
+            Some(function) if addr == function.address => format!(" <{}>", function.name),
+            Some(function) => format!(" <{}+{}>", function.name, addr - function.address),
+            None => String::new(),
+        }
+    }
+
+    /// Implements `disassemble`: prints the instructions of a function (by default the one the
+    /// selected frame is executing), interleaved with the source lines they were compiled from.
+    fn disassemble(&self, name: Option<&str>) -> Result<(), String> {
+        let inferior = self.inferior.as_ref().unwrap();
+        let frame = self.selected_frame()?;
+        let function = match name {
+            Some(name) => self
+                .debug_data
+                .get_addr_for_function(None, name)
+                .and_then(|addr| self.debug_data.get_function_containing(addr))
+                .ok_or_else(|| format!("No symbol \"{}\" in current context.", name))?,
+            None => self
+                .debug_data
+                .get_function_containing(frame.lookup_pc().wrapping_sub(inferior.load_bias()))
+                .ok_or("No function contains the program counter for the selected frame.")?,
+        };
+        let addr = function.address.wrapping_add(inferior.load_bias());
+        let bytes = inferior
+            .read_bytes(addr, function.text_length)
+            .map_err(|err| format!("Cannot access memory at address {:#x}: {}", addr, err))?;
+        println!("Dump of assembler code for function {}:", function.name);
+        self.print_instructions(addr, &bytes, usize::MAX, &frame, true);
+        println!("End of assembler dump.");
+        Ok(())
+    }
+
+    /// Decodes and prints up to `count` instructions from `bytes`, which were read from the
+    /// (runtime) address `addr`. The instruction the frame is executing is marked with `=>`. In a
+    /// function listing, offsets are relative to the function and the source lines are shown.
+    /// Returns the address following the last instruction printed.
+    fn print_instructions(
+        &self,
+        addr: usize,
+        bytes: &[u8],
+        count: usize,
+        frame: &Frame,
+        listing: bool,
+    ) -> usize {
+        let load_bias = self.inferior.as_ref().unwrap().load_bias();
+        let mut decoder = Decoder::with_ip(64, bytes, addr as u64, DecoderOptions::NONE);
+        let mut formatter = GasFormatter::new();
+        formatter.options_mut().set_first_operand_char_index(7);
+        formatter.options_mut().set_uppercase_hex(false);
+        formatter.options_mut().set_branch_leading_zeros(false);
+        let mut instruction = Instruction::default();
+        let mut last_line = None;
+        let mut source: Option<(String, Vec<String>)> = None;
+        let mut decoded = 0;
+        while decoder.can_decode() && decoded < count {
+            decoder.decode_out(&mut instruction);
+            decoded += 1;
+            let ip = instruction.ip() as usize;
+
+            if listing {
+                let line = self
+                    .debug_data
+                    .get_line_from_addr(ip.wrapping_sub(load_bias));
+                match &line {
+                    Some(line)
+                        if last_line
+                            .as_ref()
+                            .map(|last: &Line| (&last.file, last.number))
+                            != Some((&line.file, line.number)) =>
+                    {
+                        if source.as_ref().map(|(file, _)| file) != Some(&line.file) {
+                            println!("{}:", line.file);
+                            let path = self.debug_data.get_source_path(&line.file);
+                            let text = fs::read_to_string(path).unwrap_or_default();
+                            source = Some((
+                                line.file.clone(),
+                                text.lines().map(str::to_string).collect(),
+                            ));
+                        }
+                        let text = source
+                            .as_ref()
+                            .and_then(|(_, lines)| lines.get(line.number.wrapping_sub(1)))
+                            .map_or("", String::as_str);
+                        println!("{}\t{}", line.number, text);
+                    }
+                    _ => {}
+                }
+                last_line = line;
+            }
+
+            let mut text = String::new();
+            if instruction.is_invalid() {
+                text.push_str("(bad)");
+            } else {
+                formatter.format(&instruction, &mut text);
+            }
+            if let OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64 =
+                instruction.op0_kind()
+            {
+                text.push_str(&self.describe_code_address(instruction.near_branch_target()));
+            }
+            let marker = if ip == frame.pc { "=> " } else { "   " };
+            let location = if listing {
+                format!(" <+{}>", ip - addr)
+            } else {
+                self.describe_code_address(ip as u64)
+            };
+            println!("{}{:#x}{}:\t{}", marker, ip, location, text);
+        }
+        decoder.ip() as usize
+    }
+
+    /// Implements `x`: dumps `format.count` units of memory starting at `addr`, which is either a
+    /// `*0x...` address or an expression evaluating to an address.
+    fn examine(&self, format: ExamineFormat, addr: &str) -> Result<(), String> {
+        let addr = match parse_address(addr) {
+            Some(addr) => addr,
+            None => {
+                let context = self.selected_context()?;
+                Expression::parse(addr)?
+                    .evaluate(&context)?
+                    .as_address(&self.debug_data)?
+            }
+        };
+        let inferior = self.inferior.as_ref().unwrap();
+        let read = |addr: usize, len: usize| {
+            inferior
+                .read_bytes(addr, len)
+                .map_err(|err| format!("Cannot access memory at address {:#x}: {}", addr, err))
+        };
+        // Memory is read a little at a time, so that a large count can't make us allocate (or
+        // compute the end of) more than the address space holds
+        let advance = |addr: usize, len: usize| {
+            addr.checked_add(len)
+                .ok_or_else(|| format!("Cannot access memory at address {:#x}", addr))
+        };
+
+        if format.format == 'i' {
+            let frame = self.selected_frame()?;
+            let (mut addr, mut remaining) = (addr, format.count);
+            while remaining > 0 {
+                // Read as much as the instructions could take up, but stop at unreadable memory
+                let count = remaining.min(EXAMINE_CHUNK);
+                let full_len = count * MAX_INSTRUCTION_LENGTH;
+                let mut len = full_len;
+                let bytes = loop {
+                    match inferior.read_bytes(addr, len) {
+                        Ok(bytes) => break bytes,
+                        Err(_) if len > MAX_INSTRUCTION_LENGTH => len /= 2,
+                        Err(err) => {
+                            return Err(format!(
+                                "Cannot access memory at address {:#x}: {}",
+                                addr, err
+                            ))
+                        }
+                    }
+                };
+                addr = self.print_instructions(addr, &bytes, count, &frame, false);
+                if len < full_len {
+                    break;
+                }
+                remaining -= count;
+            }
+            return Ok(());
+        }
+
+        if format.format == 's' {
+            let mut addr = addr;
+            for _ in 0..format.count {
+                let mut text = Vec::new();
+                let mut terminated = false;
+                while text.len() < MAX_STRING_LENGTH {
+                    match read(advance(addr, text.len())?, 1)?.first() {
+                        Some(0) | None => {
+                            terminated = true;
+                            break;
+                        }
+                        Some(&byte) => text.push(byte),
+                    }
+                }
+                let ellipsis = if terminated { "" } else { "..." };
+                println!(
+                    "{:#x}:\t{:?}{}",
+                    addr,
+                    String::from_utf8_lossy(&text),
+                    ellipsis
+                );
+                addr = advance(addr, text.len() + 1)?;
+            }
+            return Ok(());
+        }
+
+        let per_line = match (format.format, format.size) {
+            ('c', _) | (_, 1) => 8,
+            (_, 8) => 2,
+            (_, 2) => 8,
+            _ => 4,
+        };
+        let (mut addr, mut remaining) = (addr, format.count);
+        while remaining > 0 {
+            let units = remaining.min(per_line);
+            let values: Vec<String> = read(addr, units * format.size)?
+                .chunks(format.size)
+                .map(|unit| match format.format {
+                    // Addresses are followed by the function they point into, if any
+                    'a' => {
+                        format_unit(format, unit)
+                            + &self.describe_code_address(decode_integer(unit).0)
+                    }
+                    _ => format_unit(format, unit),
+                })
+                .collect();
+            println!("{:#x}:\t{}", addr, values.join("\t"));
+            remaining -= units;
+            if remaining > 0 {
+                addr = advance(addr, units * format.size)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the source line the selected frame is executing, if the inferior is running.
+    fn selected_line(&self) -> Option<Line> {
+        let load_bias = self.inferior.as_ref()?.load_bias();
+        let frame = self.selected_frame().ok()?;
+        self.debug_data
+            .get_line_from_addr(frame.lookup_pc().wrapping_sub(load_bias))
+    }
+
+    /// Resolves a breakpoint location given as `<line>` (in the file being listed or executed),
+    /// `<file>:<line>`, `<function>` or `<file>:<function>` to an (unrelocated) address.
+    /// Breakpoints on a function are placed after its prologue; `*<function>` is taken as is.
+    /// `*<address>` is a runtime address, as shown by `bt` or `x`.
+    fn breakpoint_address(&self, location: &str) -> Result<usize, String> {
+        if let Some(name) = location.strip_prefix('*') {
+            return self
+                .debug_data
+                .get_addr_for_function(None, name)
+                .or_else(|| parse_address(location).map(|addr| addr.wrapping_sub(self.load_bias())))
+                .ok_or_else(|| format!("No symbol \"{}\" in current context.", name));
+        }
+        let (file, target) = match location.rfind(':') {
+            Some(pos) => (Some(&location[..pos]), &location[pos + 1..]),
+            None => (None, location),
+        };
+        match target.parse::<usize>() {
+            Ok(number) => {
+                let file = match file {
+                    Some(file) => Some(file.to_string()),
+                    None => self.default_list_line().map(|line| line.file),
+                };
+                self.debug_data.get_addr_for_line(file.as_deref(), number)
+            }
+            Err(_) => self
+                .debug_data
+                .get_addr_for_function(file, target)
+                .map(|addr| self.debug_data.skip_prologue(addr))
+                .ok_or_else(|| format!("Function \"{}\" not defined.", location)),
+        }
+    }
+
+    /// Returns the line `list` centers on by default: the selected frame's line, or the start of
+    /// main if nothing is running.
+    fn default_list_line(&self) -> Option<Line> {
+        if self.inferior.is_some() {
+            self.selected_line()
+        } else {
+            let main = self.debug_data.get_addr_for_function(None, "main")?;
+            self.debug_data.get_line_from_addr(main)
+        }
+    }
+
+    /// Prints a window of source lines, marking the line the selected frame is executing. With no
+    /// argument, continues after the previous listing, or centers on the selected frame's line
+    /// (or on main, if nothing is running). Otherwise, centers on the given line number or
+    /// function.
+    fn list_source(&mut self, arg: Option<&str>) {
+        let centered = |number: usize| number.saturating_sub(LIST_WINDOW / 2).max(1);
+        let (file, first_line) = match arg {
+            None => match self.list_position.take() {
+                Some(position) => position,
+                None => match self.default_list_line() {
+                    Some(line) => (line.file, centered(line.number)),
+                    None => {
+                        println!("No source file to list.");
+                        return;
+                    }
+                },
+            },
+            Some(arg) => match arg.parse::<usize>() {
+                Ok(number) => {
+                    let file = match &self.list_position {
+                        Some((file, _)) => Some(file.clone()),
+                        None => self.default_list_line().map(|line| line.file),
+                    };
+                    match file {
+                        Some(file) => (file, centered(number)),
+                        None => {
+                            println!("No source file to list.");
+                            return;
+                        }
+                    }
+                }
+                Err(_) => match self
+                    .debug_data
+                    .get_addr_for_function(None, arg)
+                    .and_then(|addr| self.debug_data.get_line_from_addr(addr))
+                {
+                    Some(line) => (line.file, centered(line.number)),
+                    None => {
+                        println!("Function \"{}\" not defined.", arg);
+                        return;
+                    }
+                },
+            },
+        };
+
+        let path = self.debug_data.get_source_path(&file);
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                println!("Could not read {}: {}", path, err);
+                return;
+            }
+        };
+        let num_lines = source.lines().count();
+        if first_line > num_lines {
+            println!(
+                "Line number {} out of range; \"{}\" has {} lines.",
+                first_line, file, num_lines
+            );
+            return;
+        }
+        let current = self
+            .selected_line()
+            .filter(|line| line.file == file)
+            .map(|line| line.number);
+        for (number, text) in (first_line..).zip(source.lines().skip(first_line - 1)) {
+            if number >= first_line + LIST_WINDOW {
+                break;
+            }
+            let marker = if current == Some(number) { "=>" } else { "  " };
+            println!("{} {:<4} {}", marker, number, text);
+        }
+        self.list_position = Some((file, first_line + LIST_WINDOW));
+    }
+
     // fn set_breakpoint(&mut self, addr: usize) {
     //     if let Some(inferior) = &mut self.inferior {
     //         inferior.set_breakpoint(addr).expect("Failed to set breakpoint");
     //     }
     // }
 }
+
+/// Returns the name and meaning of the si_code of a fault signal, or None if the code isn't one
+/// raised by a fault.
+fn fault_code_name(signal: Signal, code: i32) -> Option<(&'static str, &'static str)> {
+    let names: &[(&str, &str)] = match signal {
+        Signal::SIGSEGV => &[
+            ("SEGV_MAPERR", "address not mapped to object"),
+            ("SEGV_ACCERR", "invalid permissions for mapped object"),
+            ("SEGV_BNDERR", "failed address bound checks"),
+            ("SEGV_PKUERR", "access denied by memory protection keys"),
+        ],
+        Signal::SIGBUS => &[
+            ("BUS_ADRALN", "invalid address alignment"),
+            ("BUS_ADRERR", "nonexistent physical address"),
+            ("BUS_OBJERR", "object-specific hardware error"),
+            (
+                "BUS_MCEERR_AR",
+                "hardware memory error consumed on a machine check",
+            ),
+            (
+                "BUS_MCEERR_AO",
+                "hardware memory error detected but not consumed",
+            ),
+        ],
+        Signal::SIGFPE => &[
+            ("FPE_INTDIV", "integer divide by zero"),
+            ("FPE_INTOVF", "integer overflow"),
+            ("FPE_FLTDIV", "floating-point divide by zero"),
+            ("FPE_FLTOVF", "floating-point overflow"),
+            ("FPE_FLTUND", "floating-point underflow"),
+            ("FPE_FLTRES", "floating-point inexact result"),
+            ("FPE_FLTINV", "floating-point invalid operation"),
+            ("FPE_FLTSUB", "subscript out of range"),
+        ],
+        Signal::SIGILL => &[
+            ("ILL_ILLOPC", "illegal opcode"),
+            ("ILL_ILLOPN", "illegal operand"),
+            ("ILL_ILLADR", "illegal addressing mode"),
+            ("ILL_ILLTRP", "illegal trap"),
+            ("ILL_PRVOPC", "privileged opcode"),
+            ("ILL_PRVREG", "privileged register"),
+            ("ILL_COPROC", "coprocessor error"),
+            ("ILL_BADSTK", "internal stack error"),
+        ],
+        _ => &[],
+    };
+    // The codes of each signal are numbered from 1
+    names.get((code as usize).checked_sub(1)?).copied()
+}
+
+/// Formats one unit of memory dumped by `x` according to its format letter.
+fn format_unit(format: ExamineFormat, unit: &[u8]) -> String {
+    let (raw, signed) = decode_integer(unit);
+    match format.format {
+        'd' => signed.to_string(),
+        'u' => raw.to_string(),
+        'o' => format!("{:#o}", raw),
+        't' => format!("{:0width$b}", raw, width = 8 * unit.len()),
+        'c' => format!("{} {:?}", signed, raw as u8 as char),
+        'a' => format!("{:#x}", raw),
+        _ => format!("{:#0width$x}", raw, width = 2 + 2 * unit.len()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The codes are those of the kernel's siginfo.h, which libc doesn't have all of.
+    #[test]
+    fn test_fault_code_name() {
+        assert_eq!(
+            fault_code_name(Signal::SIGSEGV, 1),
+            Some(("SEGV_MAPERR", "address not mapped to object"))
+        );
+        assert_eq!(
+            fault_code_name(Signal::SIGSEGV, 2),
+            Some(("SEGV_ACCERR", "invalid permissions for mapped object"))
+        );
+        assert_eq!(
+            fault_code_name(Signal::SIGBUS, 1),
+            Some(("BUS_ADRALN", "invalid address alignment"))
+        );
+        assert_eq!(
+            fault_code_name(Signal::SIGFPE, 1),
+            Some(("FPE_INTDIV", "integer divide by zero"))
+        );
+        assert_eq!(
+            fault_code_name(Signal::SIGFPE, 8),
+            Some(("FPE_FLTSUB", "subscript out of range"))
+        );
+        assert_eq!(
+            fault_code_name(Signal::SIGILL, 1),
+            Some(("ILL_ILLOPC", "illegal opcode"))
+        );
+        assert_eq!(
+            fault_code_name(Signal::SIGILL, 8),
+            Some(("ILL_BADSTK", "internal stack error"))
+        );
+    }
+
+    #[test]
+    fn test_fault_code_name_unknown() {
+        // Codes for signals sent by a process are zero or negative
+        assert_eq!(fault_code_name(Signal::SIGSEGV, 0), None);
+        assert_eq!(fault_code_name(Signal::SIGSEGV, libc::SI_USER), None);
+        assert_eq!(fault_code_name(Signal::SIGSEGV, -6), None);
+        assert_eq!(fault_code_name(Signal::SIGSEGV, 100), None);
+        assert_eq!(fault_code_name(Signal::SIGSEGV, libc::SI_KERNEL), None);
+        assert_eq!(fault_code_name(Signal::SIGINT, 1), None);
+    }
+}
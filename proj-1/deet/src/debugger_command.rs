@@ -1,24 +1,194 @@
+use crate::inferior::FollowForkMode;
+use nix::sys::signal::Signal;
+use std::convert::TryFrom;
+
+/// The most units a single `x` command will display.
+const MAX_EXAMINE_COUNT: usize = 100_000;
+
+/// How `x/<count><format><size>` displays memory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExamineFormat {
+    pub count: usize,
+    /// One of gdb's format letters: x, d, u, o, t, a, c, s or i
+    pub format: char,
+    /// The size of each unit in bytes
+    pub size: usize,
+}
+
 pub enum DebuggerCommand {
+    Attach(i32),
     Backtrace,
-    Breakpoint(String),
+    Breakpoint(String, Option<String>),
     Continue,
+    Delete(usize),
+    Detach,
+    Disable(usize),
+    Disassemble(Option<String>),
+    Down(usize),
+    Enable(usize),
+    Examine(ExamineFormat, String),
+    Finish,
+    Frame(Option<usize>),
+    Handle(Signal, Vec<String>),
+    Ignore(usize, usize),
+    InfoBreakpoints,
+    InfoLocals,
+    InfoRegisters(Vec<String>),
+    InfoSignals(Option<Signal>),
+    InfoThreads,
+    List(Option<String>),
+    Next,
+    NextInstruction(usize),
+    Print(String),
     Quit,
     Run(Vec<String>),
+    SetDetachOnFork(bool),
+    SetFollowForkMode(FollowForkMode),
+    SetVariable(String, String),
+    Step,
+    StepInstruction(usize),
+    Thread(Option<usize>),
+    Up(usize),
+    Watch(String),
 }
 
 impl DebuggerCommand {
     pub fn from_tokens(tokens: &Vec<&str>) -> Option<DebuggerCommand> {
         match tokens[0] {
+            "attach" => match tokens.get(1).map(|pid| pid.parse()) {
+                Some(Ok(pid)) if tokens.len() == 2 => Some(DebuggerCommand::Attach(pid)),
+                _ => {
+                    println!("Usage: attach <pid>");
+                    None
+                }
+            },
             "bt" | "back" | "backtrace" => Some(DebuggerCommand::Backtrace),
             "b" | "break" | "breakpoint" => {
-                if tokens.len() != 2 {
-                    println!("Usage: breakpoint <*hexadecimal address>/<function>/<line number>");
-                    None
+                if tokens.len() == 2 {
+                    Some(DebuggerCommand::Breakpoint(tokens[1].to_string(), None))
+                } else if tokens.len() > 3 && tokens[2] == "if" {
+                    Some(DebuggerCommand::Breakpoint(
+                        tokens[1].to_string(),
+                        Some(tokens[3..].join(" ")),
+                    ))
                 } else {
-                    Some(DebuggerCommand::Breakpoint(tokens[1].to_string()))
+                    println!(
+                        "Usage: breakpoint <*hexadecimal address>/<function>/<line number> \
+                         [if <condition>]"
+                    );
+                    None
                 }
             }
             "c" | "cont" | "continue" => Some(DebuggerCommand::Continue),
+            "d" | "delete" => {
+                parse_breakpoint_number(tokens, "delete").map(DebuggerCommand::Delete)
+            }
+            "detach" => Some(DebuggerCommand::Detach),
+            "disas" | "disassemble" => {
+                if tokens.len() > 2 {
+                    println!("Usage: disassemble [function]");
+                    None
+                } else {
+                    Some(DebuggerCommand::Disassemble(
+                        tokens.get(1).map(|arg| arg.to_string()),
+                    ))
+                }
+            }
+            "disable" => parse_breakpoint_number(tokens, "disable").map(DebuggerCommand::Disable),
+            "down" => parse_count(tokens, "down").map(DebuggerCommand::Down),
+            "enable" => parse_breakpoint_number(tokens, "enable").map(DebuggerCommand::Enable),
+            "f" | "frame" => match tokens.get(1).map(|level| level.parse()) {
+                None => Some(DebuggerCommand::Frame(None)),
+                Some(Ok(level)) if tokens.len() == 2 => Some(DebuggerCommand::Frame(Some(level))),
+                _ => {
+                    println!("Usage: frame [level]");
+                    None
+                }
+            },
+            "fin" | "finish" => Some(DebuggerCommand::Finish),
+            "handle" => {
+                let signal = tokens.get(1).and_then(|name| parse_signal(name));
+                let actions = &tokens[tokens.len().min(2)..];
+                let valid = actions.iter().all(|action| {
+                    ["stop", "nostop", "print", "noprint", "pass", "nopass"].contains(action)
+                });
+                match signal {
+                    Some(signal) if valid => Some(DebuggerCommand::Handle(
+                        signal,
+                        actions.iter().map(|action| action.to_string()).collect(),
+                    )),
+                    _ => {
+                        println!(
+                            "Usage: handle <signal> [stop|nostop] [print|noprint] [pass|nopass]"
+                        );
+                        None
+                    }
+                }
+            }
+            "ignore" => {
+                let args = match tokens[1..] {
+                    [num, count] => match (num.parse(), count.parse()) {
+                        (Ok(num), Ok(count)) => Some((num, count)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if let Some((num, count)) = args {
+                    Some(DebuggerCommand::Ignore(num, count))
+                } else {
+                    println!("Usage: ignore <breakpoint number> <count>");
+                    None
+                }
+            }
+            "i" | "info" => match tokens.get(1) {
+                Some(&"b") | Some(&"break") | Some(&"breakpoints") => {
+                    Some(DebuggerCommand::InfoBreakpoints)
+                }
+                Some(&"locals") => Some(DebuggerCommand::InfoLocals),
+                Some(&"r") | Some(&"reg") | Some(&"registers") => {
+                    Some(DebuggerCommand::InfoRegisters(
+                        tokens[2..]
+                            .iter()
+                            .map(|name| name.trim_start_matches('$').to_string())
+                            .collect(),
+                    ))
+                }
+                Some(&"signals") => match tokens.get(2) {
+                    None => Some(DebuggerCommand::InfoSignals(None)),
+                    Some(name) => match parse_signal(name) {
+                        Some(signal) => Some(DebuggerCommand::InfoSignals(Some(signal))),
+                        None => {
+                            println!("Unknown signal `{}'.", name);
+                            None
+                        }
+                    },
+                },
+                Some(&"threads") => Some(DebuggerCommand::InfoThreads),
+                _ => {
+                    println!("Usage: info breakpoints|locals|registers|signals|threads");
+                    None
+                }
+            },
+            "l" | "list" => {
+                if tokens.len() > 2 {
+                    println!("Usage: list [<line number>/<function>]");
+                    None
+                } else {
+                    Some(DebuggerCommand::List(
+                        tokens.get(1).map(|arg| arg.to_string()),
+                    ))
+                }
+            }
+            "n" | "next" => Some(DebuggerCommand::Next),
+            "ni" | "nexti" => parse_count(tokens, "nexti").map(DebuggerCommand::NextInstruction),
+            "p" | "print" => {
+                if tokens.len() < 2 {
+                    println!("Usage: print <expression>");
+                    None
+                } else {
+                    Some(DebuggerCommand::Print(tokens[1..].join(" ")))
+                }
+            }
             "q" | "quit" => Some(DebuggerCommand::Quit),
             "r" | "run" => {
                 let args = tokens[1..].to_vec();
@@ -26,8 +196,237 @@ impl DebuggerCommand {
                     args.iter().map(|s| s.to_string()).collect(),
                 ))
             }
+            "s" | "step" => Some(DebuggerCommand::Step),
+            "si" | "stepi" => parse_count(tokens, "stepi").map(DebuggerCommand::StepInstruction),
+            "set" if tokens.get(1) == Some(&"follow-fork-mode") => match tokens[2..] {
+                ["parent"] => Some(DebuggerCommand::SetFollowForkMode(FollowForkMode::Parent)),
+                ["child"] => Some(DebuggerCommand::SetFollowForkMode(FollowForkMode::Child)),
+                _ => {
+                    println!("Usage: set follow-fork-mode parent|child");
+                    None
+                }
+            },
+            "set" if tokens.get(1) == Some(&"detach-on-fork") => match tokens[2..] {
+                ["on"] => Some(DebuggerCommand::SetDetachOnFork(true)),
+                ["off"] => Some(DebuggerCommand::SetDetachOnFork(false)),
+                _ => {
+                    println!("Usage: set detach-on-fork on|off");
+                    None
+                }
+            },
+            "set" => {
+                let assignment = match tokens.get(1) {
+                    Some(&"var") | Some(&"variable") => parse_assignment(&tokens[2..].join(" ")),
+                    Some(target) if target.starts_with('$') => {
+                        parse_assignment(&tokens[1..].join(" "))
+                    }
+                    _ => None,
+                };
+                if let Some((target, value)) = assignment {
+                    Some(DebuggerCommand::SetVariable(target, value))
+                } else {
+                    println!("Usage: set var <variable> = <value> | set $<register> = <value>");
+                    None
+                }
+            }
+            "t" | "thread" => match tokens.get(1).map(|num| num.parse()) {
+                None => Some(DebuggerCommand::Thread(None)),
+                Some(Ok(num)) if tokens.len() == 2 => Some(DebuggerCommand::Thread(Some(num))),
+                _ => {
+                    println!("Usage: thread [thread number]");
+                    None
+                }
+            },
+            "up" => parse_count(tokens, "up").map(DebuggerCommand::Up),
+            "watch" => {
+                if tokens.len() < 2 {
+                    println!("Usage: watch <expression>");
+                    None
+                } else {
+                    Some(DebuggerCommand::Watch(tokens[1..].join(" ")))
+                }
+            }
+            command if command == "x" || command.starts_with("x/") => {
+                let format = parse_examine_format(&command[1..]);
+                match format {
+                    Some(format) if tokens.len() > 1 => {
+                        Some(DebuggerCommand::Examine(format, tokens[1..].join(" ")))
+                    }
+                    _ => {
+                        println!("Usage: x/<count><format><size> <address>");
+                        None
+                    }
+                }
+            }
             // Default case:
             _ => None,
         }
     }
 }
+
+/// Parses a signal given by name (such as `SIGSEGV`) or number.
+fn parse_signal(text: &str) -> Option<Signal> {
+    match text.parse::<i32>() {
+        Ok(number) => Signal::try_from(number).ok(),
+        Err(_) => text.to_uppercase().parse().ok(),
+    }
+}
+
+fn parse_breakpoint_number(tokens: &[&str], command: &str) -> Option<usize> {
+    match tokens.get(1).map(|num| num.parse()) {
+        Some(Ok(num)) if tokens.len() == 2 => Some(num),
+        _ => {
+            println!("Usage: {} <breakpoint number>", command);
+            None
+        }
+    }
+}
+
+/// Parses the optional repeat count of `up`, `down`, `stepi` and `nexti` (1 by default).
+fn parse_count(tokens: &[&str], command: &str) -> Option<usize> {
+    match tokens.get(1).map(|count| count.parse()) {
+        None => Some(1),
+        Some(Ok(count)) if tokens.len() == 2 => Some(count),
+        _ => {
+            println!("Usage: {} [count]", command);
+            None
+        }
+    }
+}
+
+/// Splits `<target> = <value>` at the assignment, as opposed to an `==`, `<=`, `>=` or `!=`.
+fn parse_assignment(text: &str) -> Option<(String, String)> {
+    let bytes = text.as_bytes();
+    let pos = (0..bytes.len()).find(|&i| {
+        bytes[i] == b'='
+            && (i == 0 || !b"=!<>".contains(&bytes[i - 1]))
+            && bytes.get(i + 1) != Some(&b'=')
+    })?;
+    let (target, value) = (text[..pos].trim(), text[pos + 1..].trim());
+    if target.is_empty() || value.is_empty() {
+        return None;
+    }
+    Some((target.to_string(), value.to_string()))
+}
+
+/// Parses the `/<count><format><size>` suffix of `x`, such as `/4xw`. Each part is optional.
+fn parse_examine_format(spec: &str) -> Option<ExamineFormat> {
+    let mut format = ExamineFormat {
+        count: 1,
+        format: 'x',
+        size: 4,
+    };
+    if spec.is_empty() {
+        return Some(format);
+    } else if !spec.starts_with('/') {
+        return None;
+    }
+    let spec = &spec[1..];
+    let digits = spec.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 {
+        format.count = spec[..digits].parse().ok()?;
+        if format.count > MAX_EXAMINE_COUNT {
+            println!(
+                "At most {} units can be examined at once.",
+                MAX_EXAMINE_COUNT
+            );
+            return None;
+        }
+    }
+    let mut size = None;
+    for letter in spec[digits..].chars() {
+        match letter {
+            'x' | 'd' | 'u' | 'o' | 't' | 'a' | 'c' | 's' | 'i' => format.format = letter,
+            'b' => size = Some(1),
+            'h' => size = Some(2),
+            'w' => size = Some(4),
+            'g' => size = Some(8),
+            _ => return None,
+        }
+    }
+    // Like gdb, characters default to bytes and addresses are always 8 bytes
+    format.size = match format.format {
+        'a' => 8,
+        'c' => size.unwrap_or(1),
+        _ => size.unwrap_or(format.size),
+    };
+    Some(format)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assignment(target: &str, value: &str) -> Option<(String, String)> {
+        Some((target.to_string(), value.to_string()))
+    }
+
+    fn examine_format(count: usize, format: char, size: usize) -> Option<ExamineFormat> {
+        Some(ExamineFormat {
+            count,
+            format,
+            size,
+        })
+    }
+
+    #[test]
+    fn test_parse_examine_format() {
+        assert_eq!(parse_examine_format(""), examine_format(1, 'x', 4));
+        assert_eq!(parse_examine_format("/4xw"), examine_format(4, 'x', 4));
+        assert_eq!(parse_examine_format("/2g"), examine_format(2, 'x', 8));
+        assert_eq!(parse_examine_format("/d"), examine_format(1, 'd', 4));
+        assert_eq!(parse_examine_format("/10i"), examine_format(10, 'i', 4));
+        // Characters default to bytes, and addresses are always 8 bytes
+        assert_eq!(parse_examine_format("/3c"), examine_format(3, 'c', 1));
+        assert_eq!(parse_examine_format("/ab"), examine_format(1, 'a', 8));
+        // A later letter overrides an earlier one
+        assert_eq!(parse_examine_format("/bhx"), examine_format(1, 'x', 2));
+    }
+
+    #[test]
+    fn test_parse_examine_format_errors() {
+        assert_eq!(parse_examine_format("4x"), None);
+        assert_eq!(parse_examine_format("/4z"), None);
+        assert_eq!(parse_examine_format("/x4"), None);
+        assert_eq!(parse_examine_format("/-1x"), None);
+    }
+
+    #[test]
+    fn test_parse_examine_format_count_limit() {
+        let largest = format!("/{}x", MAX_EXAMINE_COUNT);
+        assert_eq!(
+            parse_examine_format(&largest),
+            examine_format(MAX_EXAMINE_COUNT, 'x', 4)
+        );
+        let too_many = format!("/{}x", MAX_EXAMINE_COUNT + 1);
+        assert_eq!(parse_examine_format(&too_many), None);
+        // Counts that would overflow when multiplied by the unit size, or don't fit at all
+        assert_eq!(parse_examine_format("/3000000000000000000i"), None);
+        assert_eq!(parse_examine_format("/1000000000000000000x"), None);
+        assert_eq!(parse_examine_format("/99999999999999999999999x"), None);
+    }
+
+    #[test]
+    fn test_parse_assignment() {
+        assert_eq!(parse_assignment("x = 5"), assignment("x", "5"));
+        assert_eq!(parse_assignment("arr[i]=x+1"), assignment("arr[i]", "x+1"));
+        assert_eq!(parse_assignment("p->next = 0"), assignment("p->next", "0"));
+        // Comparisons on either side are part of the operands
+        assert_eq!(parse_assignment("x = y == 2"), assignment("x", "y == 2"));
+        assert_eq!(parse_assignment("x = y <= 2"), assignment("x", "y <= 2"));
+        assert_eq!(
+            parse_assignment("a[i != 0] = 1"),
+            assignment("a[i != 0]", "1")
+        );
+    }
+
+    #[test]
+    fn test_parse_assignment_errors() {
+        assert_eq!(parse_assignment("x"), None);
+        assert_eq!(parse_assignment("x == 5"), None);
+        assert_eq!(parse_assignment("x >= 5"), None);
+        assert_eq!(parse_assignment("= 5"), None);
+        assert_eq!(parse_assignment("x ="), None);
+        assert_eq!(parse_assignment(""), None);
+    }
+}
@@ -1,8 +1,10 @@
 use crate::gimli_wrapper;
 use addr2line::Context;
-use object::Object;
+use object::{Object, ObjectSegment};
+use std::collections::HashMap;
 use std::convert::TryInto;
-use std::{fmt, fs};
+use std::ops::Range;
+use std::{fmt, fs, path};
 
 #[derive(Debug)]
 pub enum Error {
@@ -13,6 +15,8 @@ pub enum Error {
 pub struct DwarfData {
     files: Vec<File>,
     addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    call_frame_info: gimli_wrapper::CallFrameInfo,
+    types: HashMap<TypeId, Type>,
 }
 
 impl fmt::Debug for DwarfData {
@@ -27,55 +31,163 @@ impl From<gimli_wrapper::Error> for Error {
     }
 }
 
+fn object_endian(object: &object::File) -> gimli::RunTimeEndian {
+    if object.is_little_endian() {
+        gimli::RunTimeEndian::Little
+    } else {
+        gimli::RunTimeEndian::Big
+    }
+}
+
+/// Just the call frame information of a binary, such as a shared library the program uses, which
+/// is enough to unwind the stack through its functions.
+pub struct FrameTable {
+    /// The lowest address any segment of the binary is linked at
+    pub base_address: usize,
+    call_frame_info: gimli_wrapper::CallFrameInfo,
+}
+
+impl FrameTable {
+    pub fn from_file(path: &str) -> Result<FrameTable, Error> {
+        let file = fs::File::open(path).or(Err(Error::ErrorOpeningFile))?;
+        let mmap = unsafe { memmap::Mmap::map(&file).or(Err(Error::ErrorOpeningFile))? };
+        let object =
+            object::File::parse(&mmap).map_err(|e| gimli_wrapper::Error::Object(e.to_string()))?;
+        let base_address = object
+            .segments()
+            .map(|segment| segment.address())
+            .min()
+            .unwrap_or(0);
+        Ok(FrameTable {
+            base_address: base_address as usize,
+            call_frame_info: gimli_wrapper::CallFrameInfo::load(&object, object_endian(&object)),
+        })
+    }
+
+    /// Like `DwarfData::get_unwind_row`, for an address relative to where the binary is linked.
+    pub fn get_unwind_row(&self, curr_addr: usize) -> Option<UnwindRow> {
+        self.call_frame_info.unwind_row(curr_addr)
+    }
+}
+
 impl DwarfData {
     pub fn from_file(path: &str) -> Result<DwarfData, Error> {
         let file = fs::File::open(path).or(Err(Error::ErrorOpeningFile))?;
         let mmap = unsafe { memmap::Mmap::map(&file).or(Err(Error::ErrorOpeningFile))? };
         let object =
             object::File::parse(&mmap).map_err(|e| gimli_wrapper::Error::Object(e.to_string()))?;
-        let endian = if object.is_little_endian() {
-            gimli::RunTimeEndian::Little
-        } else {
-            gimli::RunTimeEndian::Big
-        };
+        let endian = object_endian(&object);
+        let (files, types) = gimli_wrapper::load_file(&object, endian)?;
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
+            files,
+            types,
             addr2line: Context::new(&object).map_err(gimli_wrapper::Error::from)?,
+            call_frame_info: gimli_wrapper::CallFrameInfo::load(&object, endian),
         })
     }
 
-    fn get_target_file(&self, file: &str) -> Option<&File> {
-        self.files.iter().find(|f| {
-            f.name == file || (!file.contains('/') && f.name.ends_with(&format!("/{}", file)))
-        })
+    /// Debugging information made up of the given compilation units and types, without any call
+    /// frame information or address-to-line lookups. Lets tests run without having to build one of
+    /// the sample programs.
+    #[cfg(test)]
+    pub fn from_parts(files: Vec<File>, types: HashMap<TypeId, Type>) -> DwarfData {
+        use addr2line::gimli::{EndianRcSlice, RunTimeEndian};
+        let empty = EndianRcSlice::new(std::rc::Rc::from(&[][..]), RunTimeEndian::default());
+        let addr2line = Context::from_sections(
+            empty.clone().into(),
+            empty.clone().into(),
+            empty.clone().into(),
+            empty.clone().into(),
+            empty.clone().into(),
+            empty.clone().into(),
+            empty.clone().into(),
+            empty.clone().into(),
+            empty.clone().into(),
+            empty,
+        )
+        .expect("Could not set up empty debugging information");
+        DwarfData {
+            files,
+            addr2line,
+            call_frame_info: gimli_wrapper::CallFrameInfo::default(),
+            types,
+        }
     }
 
-    pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
+    /// Finds the compilation unit for a source file, given by the name it was compiled under, by
+    /// its full path, or (if it has no directory part) by its base name. It is an error for a
+    /// name to match the main source files of several compilation units.
+    fn get_target_file(&self, file: &str) -> Result<&File, String> {
+        let mut matching = self.files.iter().filter(|unit| unit.is_named(file));
+        let target = matching
+            .next()
+            .ok_or_else(|| format!("No source file named {}.", file))?;
+        let others: Vec<_> = matching.map(|unit| unit.path()).collect();
+        if others.is_empty() {
+            Ok(target)
+        } else {
+            Err(format!(
+                "Source file name `{}' is ambiguous: {}, {}.",
+                file,
+                target.path(),
+                others.join(", ")
+            ))
+        }
+    }
+
+    /// Returns the address of the first statement of the given line, or if the line has no code
+    /// of its own, of the next line that does. Without a file, the first compilation unit is used.
+    pub fn get_addr_for_line(
+        &self,
+        file: Option<&str>,
+        line_number: usize,
+    ) -> Result<usize, String> {
         let target_file = match file {
             Some(filename) => self.get_target_file(filename)?,
-            None => self.files.get(0)?,
+            None => self
+                .files
+                .first()
+                .ok_or("No source file to set a breakpoint in.")?,
         };
-        Some(
-            target_file
-                .lines
-                .iter()
-                .find(|line| line.number >= line_number)?
-                .address,
-        )
+        // A line can be spread over several rows (and sequences) of the line table, so find the
+        // first line with code and then the lowest address among its rows
+        let number = target_file
+            .lines
+            .iter()
+            .map(|line| line.number)
+            .filter(|&number| number >= line_number)
+            .min()
+            .ok_or_else(|| {
+                format!(
+                    "Line {} is out of range for \"{}\".",
+                    line_number, target_file.name
+                )
+            })?;
+        Ok(target_file
+            .lines
+            .iter()
+            .filter(|line| line.number == number)
+            .map(|line| line.address)
+            .min()
+            .unwrap())
     }
 
+    /// Returns the entry point of a function. Declarations of functions defined elsewhere (such
+    /// as library functions) have no code and are skipped.
     pub fn get_addr_for_function(&self, file: Option<&str>, func_name: &str) -> Option<usize> {
+        let is_target = |func: &&Function| func.name == func_name && func.text_length > 0;
         match file {
             Some(filename) => Some(
-                self.get_target_file(filename)?
+                self.get_target_file(filename)
+                    .ok()?
                     .functions
                     .iter()
-                    .find(|func| func.name == func_name)?
+                    .find(is_target)?
                     .address,
             ),
             None => {
                 for file in &self.files {
-                    if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
+                    if let Some(func) = file.functions.iter().find(is_target) {
                         return Some(func.address);
                     }
                 }
@@ -84,6 +196,67 @@ impl DwarfData {
         }
     }
 
+    /// Returns the address just past the prologue of the function starting at `func_addr`, where
+    /// its stack frame has been set up and its parameters stored: the first statement of the
+    /// function that belongs to a line other than the one it starts on, as gdb does. If the
+    /// function fits on one line, its second statement is used instead.
+    pub fn skip_prologue(&self, func_addr: usize) -> usize {
+        let (file, func) = match self.find_function(func_addr) {
+            Some(found) => found,
+            None => return func_addr,
+        };
+        let mut lines: Vec<&Line> = file
+            .lines
+            .iter()
+            .filter(|line| {
+                line.address >= func.address && line.address < func.address + func.text_length
+            })
+            .collect();
+        lines.sort_by_key(|line| line.address);
+        let entry_line = match lines.first() {
+            Some(line) if line.address == func.address => line.number,
+            _ => return func_addr,
+        };
+        lines
+            .iter()
+            .find(|line| line.number != entry_line)
+            .or_else(|| lines.iter().find(|line| line.address > func.address))
+            .map_or(func_addr, |line| line.address)
+    }
+
+    /// Looks up a variable by name as seen from the given instruction address: locals and
+    /// parameters of the enclosing function shadow globals of the same compilation unit, which in
+    /// turn shadow globals of other compilation units.
+    pub fn get_variable(&self, curr_addr: usize, name: &str) -> Option<&Variable> {
+        if let Some((file, func)) = self.find_function(curr_addr) {
+            if let Some(var) = func.variables.iter().find(|var| var.name == name) {
+                return Some(var);
+            }
+            if let Some(var) = file.global_variables.iter().find(|var| var.name == name) {
+                return Some(var);
+            }
+        }
+        self.files
+            .iter()
+            .find_map(|file| file.global_variables.iter().find(|var| var.name == name))
+    }
+
+    /// Returns the function whose code contains the given instruction address.
+    pub fn get_function_containing(&self, curr_addr: usize) -> Option<&Function> {
+        Some(self.find_function(curr_addr)?.1)
+    }
+
+    fn find_function(&self, curr_addr: usize) -> Option<(&File, &Function)> {
+        self.files.iter().find_map(|file| {
+            file.functions
+                .iter()
+                .find(|func| {
+                    func.address <= curr_addr && curr_addr < func.address + func.text_length
+                })
+                .map(|func| (file, func))
+        })
+    }
+
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
         let location = self
             .addr2line
@@ -106,6 +279,222 @@ impl DwarfData {
         Some(frame.function?.raw_name().ok()?.to_string())
     }
 
+    /// Returns the path at which the given source file can be found. Relative paths (as recorded by
+    /// the compiler) are resolved against the compilation directory of the unit they belong to.
+    pub fn get_source_path(&self, file: &str) -> String {
+        let path = path::Path::new(file);
+        if path.is_absolute() {
+            return file.to_string();
+        }
+        let comp_dir = match self
+            .get_target_file(file)
+            .ok()
+            .or_else(|| self.files.first())
+        {
+            Some(unit) => &unit.comp_dir,
+            None => return file.to_string(),
+        };
+        path::Path::new(comp_dir)
+            .join(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn get_type(&self, id: TypeId) -> Option<&Type> {
+        self.types.get(&id)
+    }
+
+    /// Looks up a type by the name C code would use for it, such as `unsigned int`, `node_t` or
+    /// `struct node *`.
+    pub fn find_type(&self, name: &str) -> Option<TypeId> {
+        let name: String = name.split_whitespace().collect();
+        self.types
+            .iter()
+            .filter(|(_, entity_type)| {
+                entity_type.name.split_whitespace().collect::<String>() == name
+            })
+            .map(|(id, _)| *id)
+            // Several compilation units may describe the same type; pick one consistently
+            .min()
+    }
+
+    /// Looks up an enumerator (such as `GREEN` in `enum color { RED, GREEN }`) by name, returning
+    /// its enum type and value.
+    pub fn find_enumerator(&self, name: &str) -> Option<(TypeId, i64)> {
+        self.types
+            .iter()
+            .filter_map(|(id, entity_type)| match &entity_type.kind {
+                TypeKind::Enum(enumerators) => enumerators
+                    .iter()
+                    .find(|(enumerator, _)| enumerator == name)
+                    .map(|(_, value)| (*id, *value)),
+                _ => None,
+            })
+            .min()
+    }
+
+    /// Strips typedefs and qualifiers off a type, returning the type it stands for. (Typedefs of
+    /// void are returned as they are.)
+    pub fn resolve_type<'a>(&'a self, entity_type: &'a Type) -> &'a Type {
+        let mut resolved = entity_type;
+        for _ in 0..MAX_TYPE_DEPTH {
+            match resolved.kind {
+                TypeKind::Typedef(Some(id)) | TypeKind::Qualified(_, Some(id)) => {
+                    match self.types.get(&id) {
+                        Some(target) => resolved = target,
+                        None => break,
+                    }
+                }
+                _ => break,
+            }
+        }
+        resolved
+    }
+
+    /// Renders a value of the given type, given the raw (little-endian) bytes read out of the
+    /// inferior. Structs and arrays are printed with their members, gdb style:
+    /// `{x = 1, next = 0x5555...}`.
+    pub fn format_value(&self, entity_type: &Type, bytes: &[u8]) -> String {
+        self.format_nested_value(entity_type, bytes, 0)
+    }
+
+    fn format_nested_value(&self, entity_type: &Type, bytes: &[u8], depth: usize) -> String {
+        if depth > MAX_TYPE_DEPTH {
+            return "...".to_string();
+        }
+        let entity_type = self.resolve_type(entity_type);
+        match &entity_type.kind {
+            TypeKind::Base(encoding) => format_base(*encoding, bytes),
+            TypeKind::Pointer(_) | TypeKind::Function => {
+                format!("{:#x}", decode_integer(bytes).0)
+            }
+            TypeKind::Enum(enumerators) => {
+                let (raw, value) = decode_integer(bytes);
+                // Enumerators may be recorded sign- or zero-extended from a narrower constant, so
+                // only the bits the enum actually has are compared
+                let mask = low_bits_mask(8 * bytes.len());
+                match enumerators
+                    .iter()
+                    .find(|(_, val)| *val as u64 & mask == raw & mask)
+                {
+                    Some((name, _)) => name.clone(),
+                    None => value.to_string(),
+                }
+            }
+            TypeKind::Struct(members) | TypeKind::Union(members) => {
+                let fields: Vec<String> = members
+                    .iter()
+                    .map(|member| {
+                        let value = match self.types.get(&member.type_id) {
+                            Some(member_type) => match self.member_bytes(member, bytes) {
+                                Some(member_bytes) => {
+                                    self.format_nested_value(member_type, &member_bytes, depth + 1)
+                                }
+                                None => "<unavailable>".to_string(),
+                            },
+                            None => "<unknown type>".to_string(),
+                        };
+                        if member.name.is_empty() {
+                            value
+                        } else {
+                            format!("{} = {}", member.name, value)
+                        }
+                    })
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            }
+            TypeKind::Array { element, count } => {
+                let element = match self.types.get(element) {
+                    Some(element) if element.size > 0 => element,
+                    _ => return "{...}".to_string(),
+                };
+                let count = count.unwrap_or(0).min(bytes.len() / element.size);
+                if matches!(
+                    self.resolve_type(element).kind,
+                    TypeKind::Base(Encoding::SignedChar) | TypeKind::Base(Encoding::UnsignedChar)
+                ) {
+                    // Print char arrays as strings, up to the terminating null byte
+                    let text: String = bytes[..count]
+                        .iter()
+                        .take_while(|b| **b != 0)
+                        .map(|b| *b as char)
+                        .collect();
+                    return format!("{:?}", text);
+                }
+                let mut elements: Vec<String> = bytes
+                    .chunks(element.size)
+                    .take(count.min(MAX_ARRAY_ELEMENTS))
+                    .map(|chunk| self.format_nested_value(element, chunk, depth + 1))
+                    .collect();
+                if count > MAX_ARRAY_ELEMENTS {
+                    elements.push("...".to_string());
+                }
+                format!("{{{}}}", elements.join(", "))
+            }
+            TypeKind::Typedef(None) | TypeKind::Qualified(_, None) => "void".to_string(),
+            TypeKind::Typedef(Some(_)) | TypeKind::Qualified(_, Some(_)) => {
+                "<unknown type>".to_string()
+            }
+        }
+    }
+
+    /// Extracts the value of a member out of the bytes of the struct or union holding it, as the
+    /// bytes of a value of the member's type. Bit-fields are shifted down and masked off (and
+    /// sign-extended if their type is signed). Returns None if the bytes don't cover the member.
+    pub fn member_bytes(&self, member: &Member, bytes: &[u8]) -> Option<Vec<u8>> {
+        let member_type = self.types.get(&member.type_id)?;
+        let bits = match member.bit_size {
+            Some(bits) if bits > 0 && bits <= 64 => bits,
+            _ => {
+                let range = member.offset..member.offset + member_type.size;
+                return Some(bytes.get(range)?.to_vec());
+            }
+        };
+        let word = read_u128(bytes.get(bit_field_bytes(member, bits))?);
+        let mut value = (word >> member.bit_offset) as u64 & low_bits_mask(bits);
+        let signed = matches!(
+            self.resolve_type(member_type).kind,
+            TypeKind::Base(Encoding::Signed) | TypeKind::Base(Encoding::SignedChar)
+        );
+        if signed && bits < 64 {
+            value = ((value << (64 - bits)) as i64 >> (64 - bits)) as u64;
+        }
+        Some(value.to_le_bytes()[..member_type.size.min(8)].to_vec())
+    }
+
+    /// Stores a value of a member's type (as its little-endian bytes) into the bytes of the struct
+    /// or union holding it. Bit-fields only change the bits they occupy. Returns the range of bytes
+    /// that changed, or None if the bytes don't cover the member.
+    pub fn store_member(
+        &self,
+        member: &Member,
+        bytes: &mut [u8],
+        value: &[u8],
+    ) -> Option<Range<usize>> {
+        let bits = match member.bit_size {
+            Some(bits) if bits > 0 && bits <= 64 => bits,
+            _ => {
+                let range = member.offset..member.offset + value.len();
+                bytes.get_mut(range.clone())?.copy_from_slice(value);
+                return Some(range);
+            }
+        };
+        let range = bit_field_bytes(member, bits);
+        let target = bytes.get_mut(range.clone())?;
+        let mask = (low_bits_mask(bits) as u128) << member.bit_offset;
+        let value = (decode_integer(value).0 as u128) << member.bit_offset;
+        let word = (read_u128(target) & !mask) | (value & mask);
+        let len = target.len();
+        target.copy_from_slice(&word.to_le_bytes()[..len]);
+        Some(range)
+    }
+
+    /// Returns the call frame information rules describing how to unwind out of the frame that is
+    /// executing the given instruction address.
+    pub fn get_unwind_row(&self, curr_addr: usize) -> Option<UnwindRow> {
+        self.call_frame_info.unwind_row(curr_addr)
+    }
+
     pub fn print(&self) {
         for file in &self.files {
             println!("------");
@@ -142,29 +531,272 @@ impl DwarfData {
     }
 }
 
+/// How to compute the canonical frame address (the value of the stack pointer just before the call
+/// that created a frame): the value of a register (by DWARF register number) plus an offset.
+#[derive(Debug, Clone, Copy)]
+pub struct CfaRule {
+    pub register: u16,
+    pub offset: i64,
+}
+
+/// How to recover the value a register had in the caller's frame.
+#[derive(Debug, Clone, Copy)]
+pub enum RegisterRule {
+    Undefined,
+    SameValue,
+    /// Saved in memory at CFA + offset
+    Offset(i64),
+    /// The value is CFA + offset
+    ValOffset(i64),
+    /// Saved in another register
+    Register(u16),
+}
+
+/// One row of the call frame information table: the rules for unwinding at a given address.
+/// Registers that aren't listed follow the calling convention.
+#[derive(Debug, Clone)]
+pub struct UnwindRow {
+    pub cfa: CfaRule,
+    pub registers: Vec<(u16, RegisterRule)>,
+}
+
+/// How the bytes of a base type should be interpreted (DW_AT_encoding).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Encoding {
+    Signed,
+    Unsigned,
+    SignedChar,
+    UnsignedChar,
+    Float,
+    Boolean,
+    #[default]
+    Unknown,
+}
+
+/// Identifies a type by the offset of its entry in .debug_info, which is how DWARF entries refer to
+/// each other. Look types up with `DwarfData::get_type`.
+pub type TypeId = usize;
+
+/// Types that refer to themselves (or each other) can't be printed in full; give up past this many
+/// levels of nesting.
+pub const MAX_TYPE_DEPTH: usize = 32;
+
+/// Arrays longer than this are printed with an ellipsis.
+const MAX_ARRAY_ELEMENTS: usize = 200;
+
 #[derive(Debug, Clone, Default)]
 pub struct Type {
     pub name: String,
     pub size: usize,
+    pub kind: TypeKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeKind {
+    Base(Encoding),
+    /// A pointer (or C++ reference) to a type, or to void
+    Pointer(Option<TypeId>),
+    Struct(Vec<Member>),
+    Union(Vec<Member>),
+    /// An array of `count` elements, or of unknown length (e.g. `int arr[]`)
+    Array {
+        element: TypeId,
+        count: Option<usize>,
+    },
+    /// The enumerators and their values
+    Enum(Vec<(String, i64)>),
+    Typedef(Option<TypeId>),
+    /// `const`, `volatile` and friends
+    Qualified(Qualifier, Option<TypeId>),
+    Function,
+}
+
+impl Default for TypeKind {
+    fn default() -> Self {
+        TypeKind::Base(Encoding::Unknown)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Qualifier {
+    Const,
+    Volatile,
+    Restrict,
+    Atomic,
+}
+
+/// A member of a struct or union.
+#[derive(Debug, Clone)]
+pub struct Member {
+    /// Empty for anonymous struct and union members
+    pub name: String,
+    /// Offset in bytes from the start of the enclosing struct
+    pub offset: usize,
+    pub type_id: TypeId,
+    /// The width of a bit-field, which starts `bit_offset` bits (counting from the least
+    /// significant one) into the byte at `offset`
+    pub bit_size: Option<usize>,
+    pub bit_offset: usize,
 }
 
 impl Type {
-    pub fn new(name: String, size: usize) -> Self {
-        Type { name, size }
+    pub fn new(name: String, size: usize, kind: TypeKind) -> Self {
+        Type { name, size, kind }
     }
 }
 
+impl fmt::Display for Qualifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Qualifier::Const => "const",
+            Qualifier::Volatile => "volatile",
+            Qualifier::Restrict => "restrict",
+            Qualifier::Atomic => "_Atomic",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Renders a value of a base type, given the raw (little-endian) bytes read out of the inferior.
+fn format_base(encoding: Encoding, bytes: &[u8]) -> String {
+    let len = bytes.len().min(8);
+    let (raw, signed) = decode_integer(bytes);
+    match encoding {
+        Encoding::Signed => format!("{}", signed),
+        Encoding::Unsigned => format!("{}", raw),
+        Encoding::SignedChar => format!("{} {:?}", signed, raw as u8 as char),
+        Encoding::UnsignedChar => format!("{} {:?}", raw, raw as u8 as char),
+        Encoding::Boolean => format!("{}", raw != 0),
+        Encoding::Float if len == 4 => format!("{}", f32::from_bits(raw as u32)),
+        Encoding::Float if len == 8 => format!("{}", f64::from_bits(raw)),
+        _ => {
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("<{} bytes: {}>", bytes.len(), hex.join(" "))
+        }
+    }
+}
+
+/// Reads up to 8 little-endian bytes as an integer, returning it both zero- and sign-extended.
+pub fn decode_integer(bytes: &[u8]) -> (u64, i64) {
+    let mut buf = [0u8; 8];
+    let len = bytes.len().min(8);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    let raw = u64::from_le_bytes(buf);
+    let signed = if len > 0 && len < 8 {
+        let shift = 64 - 8 * len;
+        ((raw << shift) as i64) >> shift
+    } else {
+        raw as i64
+    };
+    (raw, signed)
+}
+
+/// Returns a mask of the lowest `bits` bits.
+fn low_bits_mask(bits: usize) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+/// Returns the bytes of the enclosing struct that hold any of the bits of a bit-field.
+fn bit_field_bytes(member: &Member, bits: usize) -> Range<usize> {
+    member.offset..member.offset + (member.bit_offset + bits).div_ceil(8)
+}
+
+/// Reads up to 16 little-endian bytes as an integer.
+fn read_u128(bytes: &[u8]) -> u128 {
+    let mut buf = [0u8; 16];
+    let len = bytes.len().min(16);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    u128::from_le_bytes(buf)
+}
+
 #[derive(Clone)]
 pub enum Location {
+    /// A fixed (link-time) address
     Address(usize),
+    /// An offset from the frame base of the enclosing function (DW_OP_fbreg)
     FramePointerOffset(isize),
+    /// Any other DWARF location expression
+    Expression(Vec<Op>),
+    /// Location expressions that each apply over a range of instruction addresses, as emitted
+    /// for optimized code. The variable is optimized out outside of these ranges.
+    List(Vec<LocationListEntry>),
+    OptimizedOut,
+}
+
+#[derive(Debug, Clone)]
+pub struct LocationListEntry {
+    /// The (link-time) addresses of the instructions this entry covers, excluding `end`
+    pub begin: usize,
+    pub end: usize,
+    pub expression: Vec<Op>,
+}
+
+/// An operation of a DWARF expression (see section 2.5 of the DWARF 5 standard). Constants are
+/// kept as 64-bit two's complement values, and branch targets are indices into the expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    /// DW_OP_addr, with a link-time address
+    Address(u64),
+    Constant(u64),
+    /// The value lives in the given register (DW_OP_reg*) rather than in memory
+    Register(u16),
+    /// Pushes the contents of a register plus an offset (DW_OP_breg*)
+    RegisterOffset(u16, i64),
+    /// Pushes the frame base plus an offset (DW_OP_fbreg)
+    FrameOffset(i64),
+    CallFrameCfa,
+    /// Replaces the address on top of the stack with the given number of bytes read from there
+    Deref(u8),
+    Pick(u8),
+    Drop,
+    Swap,
+    Rot,
+    Abs,
+    And,
+    Div,
+    Minus,
+    Mod,
+    Mul,
+    Neg,
+    Not,
+    Or,
+    Plus,
+    PlusConstant(u64),
+    Shl,
+    Shr,
+    Shra,
+    Xor,
+    Eq,
+    Ge,
+    Gt,
+    Le,
+    Lt,
+    Ne,
+    Bra(usize),
+    Skip(usize),
+    Nop,
+    /// Ends a piece of a composite location, giving its size in bytes
+    Piece(usize),
+    /// The value itself (rather than its location) is on top of the stack
+    StackValue,
+    /// The value is given by these bytes
+    ImplicitValue(Vec<u8>),
+    /// An operation we can't evaluate, such as DW_OP_entry_value, by name
+    Unsupported(String),
 }
 
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             Location::Address(addr) => write!(f, "Address({:#x})", addr),
             Location::FramePointerOffset(offset) => write!(f, "FramePointerOffset({})", offset),
+            Location::Expression(ops) => write!(f, "Expression({:?})", ops),
+            Location::List(entries) => write!(f, "LocationList({} entries)", entries.len()),
+            Location::OptimizedOut => write!(f, "OptimizedOut"),
         }
     }
 }
@@ -180,6 +812,7 @@ impl fmt::Debug for Location {
 pub struct Variable {
     pub name: String,
     pub entity_type: Type,
+    pub type_id: TypeId,
     pub location: Location,
     pub line_number: usize, // Line number in source file
 }
@@ -190,17 +823,40 @@ pub struct Function {
     pub address: usize,
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
+    pub return_type: Option<Type>,
+    /// The expression computing the frame base that DW_OP_fbreg offsets are relative to
+    pub frame_base: Vec<Op>,
     pub variables: Vec<Variable>,
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct File {
     pub name: String,
+    /// The directory the compilation unit was compiled in, which relative paths are relative to
+    pub comp_dir: String,
     pub global_variables: Vec<Variable>,
     pub functions: Vec<Function>,
     pub lines: Vec<Line>,
 }
 
+impl File {
+    /// Returns the path of the unit's main source file.
+    fn path(&self) -> String {
+        path::Path::new(&self.comp_dir)
+            .join(&self.name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Checks whether `file` names the unit's main source file, either as it was compiled, by its
+    /// full path, or (if `file` has no directory part) by its base name.
+    fn is_named(&self, file: &str) -> bool {
+        self.name == file
+            || self.path() == file
+            || (!file.contains('/') && self.name.ends_with(&format!("/{}", file)))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Line {
     pub file: String,
@@ -213,3 +869,88 @@ impl fmt::Display for Line {
         write!(f, "{}:{}", self.file, self.number)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn unit(name: &str, lines: &[(usize, usize)]) -> File {
+        File {
+            name: name.to_string(),
+            comp_dir: "/src".to_string(),
+            global_variables: Vec::new(),
+            functions: Vec::new(),
+            lines: lines
+                .iter()
+                .map(|&(number, address)| Line {
+                    file: name.to_string(),
+                    number,
+                    address,
+                })
+                .collect(),
+        }
+    }
+
+    /// `main.c` has code on lines 5 (in two places), 7 and 9, and `lib/util.c` on line 3.
+    fn debug_data() -> DwarfData {
+        DwarfData::from_parts(
+            vec![
+                unit(
+                    "main.c",
+                    &[(5, 0x1010), (7, 0x1020), (5, 0x1008), (9, 0x1030)],
+                ),
+                unit("lib/util.c", &[(3, 0x2000)]),
+            ],
+            HashMap::new(),
+        )
+    }
+
+    #[test]
+    fn test_get_addr_for_line() {
+        let debug_data = debug_data();
+        // The lowest address of a line spread over several rows
+        assert_eq!(debug_data.get_addr_for_line(None, 5), Ok(0x1008));
+        assert_eq!(debug_data.get_addr_for_line(Some("main.c"), 7), Ok(0x1020));
+        assert_eq!(
+            debug_data.get_addr_for_line(Some("/src/main.c"), 9),
+            Ok(0x1030)
+        );
+        assert_eq!(debug_data.get_addr_for_line(Some("util.c"), 3), Ok(0x2000));
+        assert_eq!(
+            debug_data.get_addr_for_line(Some("lib/util.c"), 1),
+            Ok(0x2000)
+        );
+    }
+
+    #[test]
+    fn test_get_addr_for_line_without_code() {
+        let debug_data = debug_data();
+        // Lines without code of their own fall forward to the next line that has some
+        assert_eq!(debug_data.get_addr_for_line(None, 1), Ok(0x1008));
+        assert_eq!(debug_data.get_addr_for_line(None, 6), Ok(0x1020));
+        assert_eq!(debug_data.get_addr_for_line(None, 8), Ok(0x1030));
+        assert_eq!(
+            debug_data.get_addr_for_line(None, 10),
+            Err("Line 10 is out of range for \"main.c\".".to_string())
+        );
+    }
+
+    #[test]
+    fn test_get_addr_for_line_errors() {
+        assert_eq!(
+            debug_data().get_addr_for_line(Some("other.c"), 1),
+            Err("No source file named other.c.".to_string())
+        );
+        let ambiguous = DwarfData::from_parts(
+            vec![
+                unit("a/main.c", &[(1, 0x10)]),
+                unit("b/main.c", &[(1, 0x20)]),
+            ],
+            HashMap::new(),
+        );
+        assert!(ambiguous.get_addr_for_line(Some("main.c"), 1).is_err());
+        assert_eq!(ambiguous.get_addr_for_line(Some("b/main.c"), 1), Ok(0x20));
+        let empty = DwarfData::from_parts(Vec::new(), HashMap::new());
+        assert!(empty.get_addr_for_line(None, 1).is_err());
+    }
+}
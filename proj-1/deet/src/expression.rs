@@ -0,0 +1,1285 @@
+//! Parsing and evaluation of C expressions over the inferior's variables, such as `arr[3]`,
+//! `p->next->value`, `*ptr`, `&x`, `x + y * 2` and `(long)c`. These back `print`, `watch` and the
+//! conditions attached to breakpoints (`break foo if x == 3`).
+
+use crate::dwarf_data::{decode_integer, DwarfData, Encoding, Member, Type, TypeId, TypeKind};
+use std::convert::TryFrom;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Literal(i64),
+    Variable(String),
+    /// A machine register, such as `$rax` or `$pc`
+    Register(String),
+    Unary(UnaryOp, Box<Expression>),
+    Binary(Box<Expression>, BinaryOp, Box<Expression>),
+    /// `array[index]`
+    Index(Box<Expression>, Box<Expression>),
+    /// `value.member`
+    Member(Box<Expression>, String),
+    /// `pointer->member`
+    PointerMember(Box<Expression>, String),
+    Cast(TypeName, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+    Deref,
+    AddressOf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Mod,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+/// The type named in a cast, such as `unsigned long` or `struct node *`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeName {
+    pub name: String,
+    pub pointers: usize,
+}
+
+/// What evaluating an expression needs from the debugger: the variables in scope and the
+/// inferior's memory.
+pub trait Context {
+    fn debug_data(&self) -> &DwarfData;
+
+    /// Reads the current value of a variable that is in scope.
+    fn variable(&self, name: &str) -> Result<Value, String>;
+
+    /// Reads a register (named without the `$`).
+    fn register(&self, name: &str) -> Result<Value, String>;
+
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, String>;
+}
+
+/// The result of evaluating an expression.
+#[derive(Debug, Clone)]
+pub struct Value {
+    pub value_type: Type,
+    /// The id of the value's type if it is one described by the debugging information, rather than
+    /// one made up for the result of a computation. Taking the address of a value needs it.
+    pub type_id: Option<TypeId>,
+    /// The raw (little-endian) bytes of the value
+    pub bytes: Vec<u8>,
+    /// Where the value lives in the inferior's memory, if it lives in memory at all
+    pub address: Option<usize>,
+}
+
+/// A value that arithmetic can be done on.
+#[derive(Debug, Clone, Copy)]
+enum Scalar {
+    Signed(i64),
+    Unsigned(u64),
+    Float(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Register(String),
+    Punct(&'static str),
+}
+
+/// Punctuation, longest first so that `->` isn't read as `-` followed by `>`.
+const PUNCTUATION: [&str; 26] = [
+    "->", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "(", ")", "[", "]", ".", "*", "/", "%",
+    "+", "-", "<", ">", "&", "^", "|", "!", "~",
+];
+
+/// Keywords that can only start a type name, which is how casts are told apart from
+/// parenthesized expressions.
+const TYPE_KEYWORDS: [&str; 14] = [
+    "char", "short", "int", "long", "signed", "unsigned", "float", "double", "void", "struct",
+    "union", "enum", "const", "volatile",
+];
+
+/// Binary operators by precedence, loosest binding first.
+const PRECEDENCE: [&[(&str, BinaryOp)]; 10] = [
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+    &[
+        ("<", BinaryOp::Lt),
+        ("<=", BinaryOp::Le),
+        (">", BinaryOp::Gt),
+        (">=", BinaryOp::Ge),
+    ],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[
+        ("*", BinaryOp::Mul),
+        ("/", BinaryOp::Div),
+        ("%", BinaryOp::Mod),
+    ],
+];
+
+impl Expression {
+    /// Parses a C expression.
+    pub fn parse(input: &str) -> Result<Expression, String> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+        };
+        let expr = parser.parse_binary(0)?;
+        if parser.pos != parser.tokens.len() {
+            return Err(format!("Junk at end of expression: {}", input));
+        }
+        Ok(expr)
+    }
+
+    /// Returns the names of all the variables the expression refers to.
+    pub fn variables(&self) -> Vec<&str> {
+        match self {
+            Expression::Literal(_) | Expression::Register(_) => Vec::new(),
+            Expression::Variable(name) => vec![name],
+            Expression::Unary(_, operand)
+            | Expression::Member(operand, _)
+            | Expression::PointerMember(operand, _)
+            | Expression::Cast(_, operand) => operand.variables(),
+            Expression::Binary(lhs, _, rhs) | Expression::Index(lhs, rhs) => {
+                let mut variables = lhs.variables();
+                variables.extend(rhs.variables());
+                variables
+            }
+        }
+    }
+
+    /// Evaluates the expression against the stopped inferior.
+    pub fn evaluate(&self, context: &dyn Context) -> Result<Value, String> {
+        let debug_data = context.debug_data();
+        match self {
+            Expression::Literal(value) => {
+                let size = if *value as i32 as i64 == *value { 4 } else { 8 };
+                Ok(Value::integer(*value as u64, size, false))
+            }
+            Expression::Variable(name) => context.variable(name),
+            Expression::Register(name) => context.register(name),
+            Expression::Unary(UnaryOp::Deref, operand) => operand.evaluate(context)?.deref(context),
+            Expression::Unary(UnaryOp::AddressOf, operand) => {
+                let value = operand.evaluate(context)?;
+                let addr = value
+                    .address
+                    .ok_or("Attempt to take address of value not located in memory.")?;
+                Ok(Value {
+                    value_type: Type::new(
+                        format!("{} *", value.value_type.name),
+                        8,
+                        TypeKind::Pointer(value.type_id),
+                    ),
+                    type_id: None,
+                    bytes: (addr as u64).to_le_bytes().to_vec(),
+                    address: None,
+                })
+            }
+            Expression::Unary(op, operand) => {
+                let value = operand.evaluate(context)?;
+                let size = value.value_type.size.clamp(4, 8);
+                match (op, value.scalar(debug_data)?) {
+                    (UnaryOp::Not, scalar) => Ok(Value::truth(!scalar.is_true())),
+                    (UnaryOp::Neg, Scalar::Float(value)) => Ok(Value::double(-value)),
+                    (UnaryOp::Neg, Scalar::Signed(value)) => {
+                        Ok(Value::integer(value.wrapping_neg() as u64, size, false))
+                    }
+                    (UnaryOp::Neg, Scalar::Unsigned(value)) => {
+                        Ok(Value::integer(value.wrapping_neg(), size, true))
+                    }
+                    (UnaryOp::BitNot, Scalar::Signed(value)) => {
+                        Ok(Value::integer(!value as u64, size, false))
+                    }
+                    (UnaryOp::BitNot, Scalar::Unsigned(value)) => {
+                        Ok(Value::integer(!value, size, true))
+                    }
+                    _ => Err("Argument to complement operation not an integer.".to_string()),
+                }
+            }
+            // && and || only evaluate their right-hand side if they need to
+            Expression::Binary(lhs, op @ BinaryOp::And, rhs)
+            | Expression::Binary(lhs, op @ BinaryOp::Or, rhs) => {
+                let lhs = lhs.evaluate(context)?.scalar(debug_data)?.is_true();
+                if lhs == (*op == BinaryOp::Or) {
+                    return Ok(Value::truth(lhs));
+                }
+                let rhs = rhs.evaluate(context)?.scalar(debug_data)?.is_true();
+                Ok(Value::truth(rhs))
+            }
+            Expression::Binary(lhs, op, rhs) => {
+                let lhs = lhs.evaluate(context)?;
+                let rhs = rhs.evaluate(context)?;
+                binary(lhs, *op, rhs, debug_data)
+            }
+            Expression::Index(array, index) => {
+                let array = array.evaluate(context)?;
+                let index = match index.evaluate(context)?.scalar(debug_data)? {
+                    Scalar::Signed(index) => index,
+                    Scalar::Unsigned(index) => index as i64,
+                    Scalar::Float(_) => return Err("Array index must be an integer.".to_string()),
+                };
+                match debug_data.resolve_type(&array.value_type).kind {
+                    // Arrays that aren't in memory (e.g. kept in registers) can still be indexed
+                    TypeKind::Array { element, .. } if array.address.is_none() => {
+                        let element_type = debug_data
+                            .get_type(element)
+                            .ok_or("Unknown array element type.")?;
+                        let bytes = usize::try_from(index)
+                            .ok()
+                            .and_then(|index| index.checked_mul(element_type.size))
+                            .and_then(|start| {
+                                array
+                                    .bytes
+                                    .get(start..start.checked_add(element_type.size)?)
+                            })
+                            .ok_or("No such array element.")?;
+                        Ok(Value {
+                            value_type: element_type.clone(),
+                            type_id: Some(element),
+                            bytes: bytes.to_vec(),
+                            address: None,
+                        })
+                    }
+                    _ => {
+                        let offset = Value::integer(index as u64, 8, false);
+                        binary(array, BinaryOp::Add, offset, debug_data)
+                            .map_err(|_| "Cannot subscript requested type.".to_string())?
+                            .deref(context)
+                    }
+                }
+            }
+            Expression::Member(value, name) => value.evaluate(context)?.member(name, debug_data),
+            Expression::PointerMember(pointer, name) => {
+                pointed_to_struct(pointer.evaluate(context)?, context)?.member(name, debug_data)
+            }
+            Expression::Cast(type_name, operand) => {
+                let value = operand.evaluate(context)?;
+                let (target_type, type_id) = resolve_type_name(type_name, debug_data)?;
+                cast(value, target_type, type_id, debug_data)
+            }
+        }
+    }
+
+    /// If the expression is a bit-field (`value.bits` or `pointer->bits`), returns the struct or
+    /// union holding it along with the bit-field. Bit-fields have no address of their own, so
+    /// changing one means rewriting its bits in the holder.
+    pub fn bit_field(&self, context: &dyn Context) -> Result<Option<(Value, Member)>, String> {
+        let (holder, name) = match self {
+            Expression::Member(value, name) => (value.evaluate(context)?, name),
+            Expression::PointerMember(pointer, name) => (
+                pointed_to_struct(pointer.evaluate(context)?, context)?,
+                name,
+            ),
+            _ => return Ok(None),
+        };
+        let member = holder.find_member(name, context.debug_data())?;
+        Ok(member.bit_size.map(|_| (holder, member)))
+    }
+
+    /// Evaluates the expression as a condition, which holds if its value is nonzero.
+    pub fn is_true(&self, context: &dyn Context) -> Result<bool, String> {
+        Ok(self
+            .evaluate(context)?
+            .scalar(context.debug_data())?
+            .is_true())
+    }
+}
+
+impl Value {
+    fn integer(value: u64, size: usize, unsigned: bool) -> Value {
+        let name = match (size, unsigned) {
+            (8, false) => "long",
+            (8, true) => "unsigned long",
+            (_, false) => "int",
+            (_, true) => "unsigned int",
+        };
+        let encoding = if unsigned {
+            Encoding::Unsigned
+        } else {
+            Encoding::Signed
+        };
+        Value {
+            value_type: Type::new(name.to_string(), size, TypeKind::Base(encoding)),
+            type_id: None,
+            bytes: value.to_le_bytes()[..size].to_vec(),
+            address: None,
+        }
+    }
+
+    fn double(value: f64) -> Value {
+        Value {
+            value_type: Type::new("double".to_string(), 8, TypeKind::Base(Encoding::Float)),
+            type_id: None,
+            bytes: value.to_le_bytes().to_vec(),
+            address: None,
+        }
+    }
+
+    /// The result of a comparison: an int that is 1 or 0.
+    fn truth(value: bool) -> Value {
+        Value::integer(value as u64, 4, false)
+    }
+
+    /// Reads the value of type `type_id` that lives at `addr`.
+    fn load(type_id: TypeId, addr: usize, context: &dyn Context) -> Result<Value, String> {
+        let value_type = context
+            .debug_data()
+            .get_type(type_id)
+            .ok_or("Attempt to take contents of a value of unknown type.")?;
+        Ok(Value {
+            value_type: value_type.clone(),
+            type_id: Some(type_id),
+            bytes: context.read_memory(addr, value_type.size)?,
+            address: Some(addr),
+        })
+    }
+
+    fn deref(&self, context: &dyn Context) -> Result<Value, String> {
+        match context.debug_data().resolve_type(&self.value_type).kind {
+            TypeKind::Pointer(Some(pointee)) => {
+                Value::load(pointee, decode_integer(&self.bytes).0 as usize, context)
+            }
+            TypeKind::Pointer(None) => {
+                Err("Attempt to take contents of a void pointer.".to_string())
+            }
+            // An array used as a pointer points to its first element
+            TypeKind::Array { element, .. } => match self.address {
+                Some(addr) => Value::load(element, addr, context),
+                None => Err("Attempt to take address of value not located in memory.".to_string()),
+            },
+            _ => Err("Attempt to take contents of a non-pointer value.".to_string()),
+        }
+    }
+
+    /// Looks up a member of a struct or union value by name.
+    fn find_member(&self, name: &str, debug_data: &DwarfData) -> Result<Member, String> {
+        let members = match &debug_data.resolve_type(&self.value_type).kind {
+            TypeKind::Struct(members) | TypeKind::Union(members) => members,
+            _ => {
+                return Err(
+                    "Attempt to extract a component of a value that is not a structure."
+                        .to_string(),
+                )
+            }
+        };
+        find_member(members, name, debug_data, 0)
+            .ok_or_else(|| format!("There is no member named {}.", name))
+    }
+
+    fn member(&self, name: &str, debug_data: &DwarfData) -> Result<Value, String> {
+        let member = self.find_member(name, debug_data)?;
+        let member_type = debug_data
+            .get_type(member.type_id)
+            .ok_or_else(|| format!("Member {} has an unknown type.", name))?;
+        let bytes = debug_data
+            .member_bytes(&member, &self.bytes)
+            .ok_or_else(|| format!("Member {} is out of bounds.", name))?;
+        Ok(Value {
+            value_type: member_type.clone(),
+            type_id: Some(member.type_id),
+            bytes,
+            // Bit-fields don't start at a byte boundary, so they can't be pointed to
+            address: match member.bit_size {
+                Some(_) => None,
+                None => self.address.map(|addr| addr + member.offset),
+            },
+        })
+    }
+
+    /// Interprets the value as an address in the inferior, for commands such as `x` that take
+    /// one. Arrays stand for the address of their first element.
+    pub fn as_address(&self, debug_data: &DwarfData) -> Result<usize, String> {
+        match self.scalar(debug_data)? {
+            Scalar::Float(_) => Err("Invalid floating value found in program.".to_string()),
+            scalar => Ok(scalar.as_u64() as usize),
+        }
+    }
+
+    /// Interprets the value as a number. Pointers (and arrays, which decay to pointers) are
+    /// unsigned addresses.
+    fn scalar(&self, debug_data: &DwarfData) -> Result<Scalar, String> {
+        let (raw, signed) = decode_integer(&self.bytes);
+        match debug_data.resolve_type(&self.value_type).kind {
+            TypeKind::Base(Encoding::Signed)
+            | TypeKind::Base(Encoding::SignedChar)
+            | TypeKind::Enum(_) => Ok(Scalar::Signed(signed)),
+            TypeKind::Base(Encoding::Unsigned)
+            | TypeKind::Base(Encoding::UnsignedChar)
+            | TypeKind::Base(Encoding::Boolean)
+            | TypeKind::Pointer(_)
+            | TypeKind::Function => Ok(Scalar::Unsigned(raw)),
+            TypeKind::Base(Encoding::Float) => match self.bytes.len() {
+                4 => Ok(Scalar::Float(f32::from_bits(raw as u32) as f64)),
+                8 => Ok(Scalar::Float(f64::from_bits(raw))),
+                size => Err(format!("Unsupported {}-byte floating point value.", size)),
+            },
+            TypeKind::Array { .. } => match self.address {
+                Some(addr) => Ok(Scalar::Unsigned(addr as u64)),
+                None => Err("Attempt to take address of value not located in memory.".to_string()),
+            },
+            _ => Err(format!(
+                "Value of type `{}' is not a number.",
+                self.value_type.name
+            )),
+        }
+    }
+
+    /// If the value is a pointer (or an array), returns the type it points to and that type's
+    /// size, which pointer arithmetic is scaled by. Arithmetic on void pointers is done in bytes.
+    fn pointee(&self, debug_data: &DwarfData) -> Option<(Option<TypeId>, usize)> {
+        let pointee = match debug_data.resolve_type(&self.value_type).kind {
+            TypeKind::Pointer(pointee) => pointee,
+            TypeKind::Array { element, .. } => Some(element),
+            _ => return None,
+        };
+        let size = pointee
+            .and_then(|pointee| debug_data.get_type(pointee))
+            .map_or(1, |pointee| pointee.size.max(1));
+        Some((pointee, size))
+    }
+}
+
+impl Scalar {
+    fn is_true(self) -> bool {
+        match self {
+            Scalar::Signed(value) => value != 0,
+            Scalar::Unsigned(value) => value != 0,
+            Scalar::Float(value) => value != 0.0,
+        }
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            Scalar::Signed(value) => value as f64,
+            Scalar::Unsigned(value) => value as f64,
+            Scalar::Float(value) => value,
+        }
+    }
+
+    fn as_u64(self) -> u64 {
+        match self {
+            Scalar::Signed(value) => value as u64,
+            Scalar::Unsigned(value) => value,
+            Scalar::Float(value) => value as i64 as u64,
+        }
+    }
+}
+
+/// Evaluates a binary operator other than && and ||, following C's rules for pointer arithmetic
+/// and (roughly) its usual arithmetic conversions.
+fn binary(lhs: Value, op: BinaryOp, rhs: Value, debug_data: &DwarfData) -> Result<Value, String> {
+    match (op, lhs.pointee(debug_data), rhs.pointee(debug_data)) {
+        (BinaryOp::Add, Some(pointee), None) | (BinaryOp::Sub, Some(pointee), None) => {
+            return offset_pointer(&lhs, pointee, op, &rhs, debug_data)
+        }
+        (BinaryOp::Add, None, Some(pointee)) => {
+            return offset_pointer(&rhs, pointee, op, &lhs, debug_data)
+        }
+        (BinaryOp::Sub, Some((_, size)), Some(_)) => {
+            let lhs = lhs.scalar(debug_data)?.as_u64() as i64;
+            let rhs = rhs.scalar(debug_data)?.as_u64() as i64;
+            return Ok(Value::integer(
+                (lhs.wrapping_sub(rhs) / size as i64) as u64,
+                8,
+                false,
+            ));
+        }
+        _ => {}
+    }
+
+    let (lhs_size, rhs_size) = (lhs.value_type.size, rhs.value_type.size);
+    match (lhs.scalar(debug_data)?, rhs.scalar(debug_data)?) {
+        (Scalar::Float(_), _) | (_, Scalar::Float(_)) => {
+            let (lhs, rhs) = (
+                lhs.scalar(debug_data)?.as_f64(),
+                rhs.scalar(debug_data)?.as_f64(),
+            );
+            let result = match op {
+                BinaryOp::Mul => lhs * rhs,
+                BinaryOp::Div => lhs / rhs,
+                BinaryOp::Add => lhs + rhs,
+                BinaryOp::Sub => lhs - rhs,
+                BinaryOp::Lt => return Ok(Value::truth(lhs < rhs)),
+                BinaryOp::Le => return Ok(Value::truth(lhs <= rhs)),
+                BinaryOp::Gt => return Ok(Value::truth(lhs > rhs)),
+                BinaryOp::Ge => return Ok(Value::truth(lhs >= rhs)),
+                BinaryOp::Eq => return Ok(Value::truth(lhs == rhs)),
+                BinaryOp::Ne => return Ok(Value::truth(lhs != rhs)),
+                _ => return Err("Integer only operation.".to_string()),
+            };
+            Ok(Value::double(result))
+        }
+        (lhs, rhs) => {
+            // The result is as wide as the widest operand (but at least an int), and unsigned if
+            // an unsigned operand is that wide
+            let size = lhs_size.max(rhs_size).clamp(4, 8);
+            let unsigned = match (lhs, rhs) {
+                (Scalar::Unsigned(_), _) if lhs_size >= size => true,
+                (_, Scalar::Unsigned(_)) if rhs_size >= size => true,
+                _ => false,
+            };
+            let (a, b) = (lhs.as_u64(), rhs.as_u64());
+            let (signed_a, signed_b) = (a as i64, b as i64);
+            let result = match op {
+                BinaryOp::Mul => a.wrapping_mul(b),
+                BinaryOp::Div | BinaryOp::Mod if b == 0 => {
+                    return Err("Division by zero".to_string())
+                }
+                BinaryOp::Div if unsigned => a / b,
+                BinaryOp::Div => signed_a.wrapping_div(signed_b) as u64,
+                BinaryOp::Mod if unsigned => a % b,
+                BinaryOp::Mod => signed_a.wrapping_rem(signed_b) as u64,
+                BinaryOp::Add => a.wrapping_add(b),
+                BinaryOp::Sub => a.wrapping_sub(b),
+                BinaryOp::Shl => a.checked_shl(b as u32).unwrap_or(0),
+                BinaryOp::Shr if unsigned => a.checked_shr(b as u32).unwrap_or(0),
+                BinaryOp::Shr => (signed_a >> b.min(63)) as u64,
+                BinaryOp::BitAnd => a & b,
+                BinaryOp::BitXor => a ^ b,
+                BinaryOp::BitOr => a | b,
+                BinaryOp::Lt if unsigned => return Ok(Value::truth(a < b)),
+                BinaryOp::Le if unsigned => return Ok(Value::truth(a <= b)),
+                BinaryOp::Gt if unsigned => return Ok(Value::truth(a > b)),
+                BinaryOp::Ge if unsigned => return Ok(Value::truth(a >= b)),
+                BinaryOp::Lt => return Ok(Value::truth(signed_a < signed_b)),
+                BinaryOp::Le => return Ok(Value::truth(signed_a <= signed_b)),
+                BinaryOp::Gt => return Ok(Value::truth(signed_a > signed_b)),
+                BinaryOp::Ge => return Ok(Value::truth(signed_a >= signed_b)),
+                BinaryOp::Eq => return Ok(Value::truth(a == b)),
+                BinaryOp::Ne => return Ok(Value::truth(a != b)),
+                BinaryOp::And | BinaryOp::Or => unreachable!(),
+            };
+            Ok(Value::integer(result, size, unsigned))
+        }
+    }
+}
+
+/// Adds (or subtracts) an integer to a pointer, in units of the pointed-to type.
+fn offset_pointer(
+    pointer: &Value,
+    (pointee, size): (Option<TypeId>, usize),
+    op: BinaryOp,
+    offset: &Value,
+    debug_data: &DwarfData,
+) -> Result<Value, String> {
+    let base = pointer.scalar(debug_data)?.as_u64();
+    let offset = match offset.scalar(debug_data)? {
+        Scalar::Float(_) => {
+            return Err("Cannot add a floating point value to a pointer.".to_string())
+        }
+        offset => offset.as_u64().wrapping_mul(size as u64),
+    };
+    let addr = if op == BinaryOp::Sub {
+        base.wrapping_sub(offset)
+    } else {
+        base.wrapping_add(offset)
+    };
+    // Arrays decay to pointers to their first element
+    let value_type = match debug_data.resolve_type(&pointer.value_type).kind {
+        TypeKind::Pointer(_) => pointer.value_type.clone(),
+        _ => {
+            let name = pointee
+                .and_then(|pointee| debug_data.get_type(pointee))
+                .map_or("void".to_string(), |pointee| pointee.name.clone());
+            Type::new(format!("{} *", name), 8, TypeKind::Pointer(pointee))
+        }
+    };
+    Ok(Value {
+        value_type,
+        type_id: None,
+        bytes: addr.to_le_bytes().to_vec(),
+        address: None,
+    })
+}
+
+/// Converts a value to another type, as C does for casts and assignments. Structs, unions and
+/// arrays can only be "converted" to their own type.
+pub fn cast(
+    value: Value,
+    target_type: Type,
+    type_id: Option<TypeId>,
+    debug_data: &DwarfData,
+) -> Result<Value, String> {
+    let target_kind = &debug_data.resolve_type(&target_type).kind;
+    if let TypeKind::Struct(_) | TypeKind::Union(_) | TypeKind::Array { .. } = target_kind {
+        let source_type = debug_data.resolve_type(&value.value_type);
+        if source_type.name != debug_data.resolve_type(&target_type).name
+            || source_type.size != target_type.size
+        {
+            return Err("Invalid cast.".to_string());
+        }
+        return Ok(Value {
+            value_type: target_type,
+            type_id,
+            ..value
+        });
+    }
+    let scalar = value.scalar(debug_data)?;
+    let bytes = match target_kind {
+        TypeKind::Base(Encoding::Float) => match target_type.size {
+            4 => (scalar.as_f64() as f32).to_le_bytes().to_vec(),
+            8 => scalar.as_f64().to_le_bytes().to_vec(),
+            _ => return Err("Invalid cast.".to_string()),
+        },
+        TypeKind::Base(_) | TypeKind::Enum(_) | TypeKind::Pointer(_) if target_type.size <= 8 => {
+            scalar.as_u64().to_le_bytes()[..target_type.size].to_vec()
+        }
+        _ => return Err("Invalid cast.".to_string()),
+    };
+    Ok(Value {
+        value_type: target_type,
+        type_id,
+        bytes,
+        address: None,
+    })
+}
+
+/// The struct or union a pointer points to, for `pointer->member`.
+fn pointed_to_struct(pointer: Value, context: &dyn Context) -> Result<Value, String> {
+    match context.debug_data().resolve_type(&pointer.value_type).kind {
+        TypeKind::Pointer(_) => pointer.deref(context),
+        _ => Err(format!(
+            "The -> operator needs a pointer, not a value of type `{}'.",
+            pointer.value_type.name
+        )),
+    }
+}
+
+/// Finds a member of a struct or union by name, looking inside anonymous struct and union
+/// members. The offset of a member found inside another one is made relative to the outer
+/// struct.
+fn find_member(
+    members: &[Member],
+    name: &str,
+    debug_data: &DwarfData,
+    depth: usize,
+) -> Option<Member> {
+    if depth > crate::dwarf_data::MAX_TYPE_DEPTH {
+        return None;
+    }
+    members.iter().find_map(|member| {
+        if member.name == name {
+            return Some(member.clone());
+        }
+        if !member.name.is_empty() {
+            return None;
+        }
+        match &debug_data
+            .resolve_type(debug_data.get_type(member.type_id)?)
+            .kind
+        {
+            TypeKind::Struct(inner) | TypeKind::Union(inner) => {
+                find_member(inner, name, debug_data, depth + 1).map(|inner| Member {
+                    offset: member.offset + inner.offset,
+                    ..inner
+                })
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Looks up the type named in a cast. Pointers to types the program never points to are made up.
+fn resolve_type_name(
+    type_name: &TypeName,
+    debug_data: &DwarfData,
+) -> Result<(Type, Option<TypeId>), String> {
+    let full_name = type_name.to_string();
+    if let Some(id) = debug_data.find_type(&full_name) {
+        return Ok((debug_data.get_type(id).unwrap().clone(), Some(id)));
+    }
+    let mut pointee = None;
+    let mut name = type_name.name.clone();
+    if name != "void" {
+        let id = match debug_data.find_type(&name) {
+            Some(id) => id,
+            // The program may never use a base type we can still cast to
+            None if type_name.pointers == 0 => {
+                return builtin_type(&name)
+                    .map(|builtin| (builtin, None))
+                    .ok_or_else(|| format!("No type \"{}\" in the program.", name))
+            }
+            None => return Err(format!("No type \"{}\" in the program.", name)),
+        };
+        if type_name.pointers == 0 {
+            return Ok((debug_data.get_type(id).unwrap().clone(), Some(id)));
+        }
+        pointee = Some(id);
+    } else if type_name.pointers == 0 {
+        return Err("Invalid cast.".to_string());
+    }
+    for level in 0..type_name.pointers {
+        name.push_str(" *");
+        if level + 1 < type_name.pointers {
+            pointee = Some(
+                debug_data
+                    .find_type(&name)
+                    .ok_or_else(|| format!("No type \"{}\" in the program.", name))?,
+            );
+        }
+    }
+    Ok((Type::new(name, 8, TypeKind::Pointer(pointee)), None))
+}
+
+/// Returns the C base type with the given name, as laid out on x86-64.
+fn builtin_type(name: &str) -> Option<Type> {
+    let words: Vec<&str> = name.split_whitespace().collect();
+    let unsigned = words.contains(&"unsigned");
+    let (size, encoding) = match words.last()? {
+        _ if words.contains(&"char") && unsigned => (1, Encoding::UnsignedChar),
+        _ if words.contains(&"char") => (1, Encoding::SignedChar),
+        &"double" if words.contains(&"long") => return None,
+        &"float" => (4, Encoding::Float),
+        &"double" => (8, Encoding::Float),
+        _ if words.contains(&"short") => (2, Encoding::Signed),
+        _ if words.contains(&"long") => (8, Encoding::Signed),
+        &"int" | &"signed" | &"unsigned" => (4, Encoding::Signed),
+        _ => return None,
+    };
+    let encoding = match encoding {
+        Encoding::Signed if unsigned => Encoding::Unsigned,
+        encoding => encoding,
+    };
+    Some(Type::new(name.to_string(), size, TypeKind::Base(encoding)))
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Literal(value) => write!(f, "{}", value),
+            Expression::Variable(name) => write!(f, "{}", name),
+            Expression::Register(name) => write!(f, "${}", name),
+            Expression::Unary(op, operand) => {
+                let op = match op {
+                    UnaryOp::Neg => "-",
+                    UnaryOp::Not => "!",
+                    UnaryOp::BitNot => "~",
+                    UnaryOp::Deref => "*",
+                    UnaryOp::AddressOf => "&",
+                };
+                write!(f, "{}", op)?;
+                write_operand(f, operand)
+            }
+            Expression::Binary(lhs, op, rhs) => {
+                write_operand(f, lhs)?;
+                let op = PRECEDENCE
+                    .iter()
+                    .flat_map(|level| level.iter())
+                    .find(|(_, binary_op)| binary_op == op)
+                    .unwrap()
+                    .0;
+                write!(f, " {} ", op)?;
+                write_operand(f, rhs)
+            }
+            Expression::Index(array, index) => {
+                write_operand(f, array)?;
+                write!(f, "[{}]", index)
+            }
+            Expression::Member(value, name) => {
+                write_operand(f, value)?;
+                write!(f, ".{}", name)
+            }
+            Expression::PointerMember(pointer, name) => {
+                write_operand(f, pointer)?;
+                write!(f, "->{}", name)
+            }
+            Expression::Cast(type_name, operand) => {
+                write!(f, "({})", type_name)?;
+                write_operand(f, operand)
+            }
+        }
+    }
+}
+
+/// Writes a subexpression, parenthesizing it unless it is a plain value.
+fn write_operand(f: &mut fmt::Formatter<'_>, operand: &Expression) -> fmt::Result {
+    match operand {
+        Expression::Literal(_)
+        | Expression::Variable(_)
+        | Expression::Register(_)
+        | Expression::Index(..)
+        | Expression::Member(..)
+        | Expression::PointerMember(..) => write!(f, "{}", operand),
+        _ => write!(f, "({})", operand),
+    }
+}
+
+impl fmt::Display for TypeName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        for _ in 0..self.pointers {
+            write!(f, " *")?;
+        }
+        Ok(())
+    }
+}
+
+/// A recursive descent parser over the tokens of an expression.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    /// Consumes the next token if it is the given punctuation.
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Punct(next)) if *next == punct) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), String> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(format!("Expected '{}' in expression", punct))
+        }
+    }
+
+    /// Parses binary operators binding at least as tightly as `PRECEDENCE[level]`.
+    fn parse_binary(&mut self, level: usize) -> Result<Expression, String> {
+        if level == PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+        let mut lhs = self.parse_binary(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Punct(punct)) => PRECEDENCE[level]
+                    .iter()
+                    .find(|(name, _)| name == punct)
+                    .map(|(_, op)| *op),
+                _ => None,
+            };
+            match op {
+                Some(op) => {
+                    self.pos += 1;
+                    let rhs = self.parse_binary(level + 1)?;
+                    lhs = Expression::Binary(Box::new(lhs), op, Box::new(rhs));
+                }
+                None => return Ok(lhs),
+            }
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, String> {
+        let op = match self.peek() {
+            Some(Token::Punct("-")) => Some(UnaryOp::Neg),
+            Some(Token::Punct("!")) => Some(UnaryOp::Not),
+            Some(Token::Punct("~")) => Some(UnaryOp::BitNot),
+            Some(Token::Punct("*")) => Some(UnaryOp::Deref),
+            Some(Token::Punct("&")) => Some(UnaryOp::AddressOf),
+            _ => None,
+        };
+        if let Some(op) = op {
+            self.pos += 1;
+            let operand = self.parse_unary()?;
+            return Ok(match (op, operand) {
+                (UnaryOp::Neg, Expression::Literal(value)) => Expression::Literal(-value),
+                (op, operand) => Expression::Unary(op, Box::new(operand)),
+            });
+        }
+        if let Some(type_name) = self.parse_cast()? {
+            let operand = self.parse_unary()?;
+            return Ok(Expression::Cast(type_name, Box::new(operand)));
+        }
+        self.parse_postfix()
+    }
+
+    /// Parses `(<type name>)` if the tokens ahead look like a cast rather than a parenthesized
+    /// expression: the type starts with a keyword or ends in a `*`, or is a lone (typedef) name
+    /// followed by an operand.
+    fn parse_cast(&mut self) -> Result<Option<TypeName>, String> {
+        if self.peek() != Some(&Token::Punct("(")) {
+            return Ok(None);
+        }
+        let mut words = Vec::new();
+        let mut pointers = 0;
+        let mut end = self.pos + 1;
+        loop {
+            match self.tokens.get(end) {
+                Some(Token::Identifier(word)) if pointers == 0 => words.push(word.clone()),
+                Some(Token::Punct("*")) if !words.is_empty() => pointers += 1,
+                Some(Token::Punct(")")) if !words.is_empty() => break,
+                _ => return Ok(None),
+            }
+            end += 1;
+        }
+        let starts_operand = matches!(
+            self.tokens.get(end + 1),
+            Some(Token::Identifier(_))
+                | Some(Token::Number(_))
+                | Some(Token::Register(_))
+                | Some(Token::Punct("("))
+        );
+        let is_cast = TYPE_KEYWORDS.contains(&words[0].as_str())
+            || pointers > 0
+            || (words.len() == 1 && starts_operand);
+        if !is_cast {
+            return Ok(None);
+        }
+        self.pos = end + 1;
+        Ok(Some(TypeName {
+            name: words.join(" "),
+            pointers,
+        }))
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expression, String> {
+        let mut expr = self.parse_primary()?;
+        loop {
+            if self.eat("[") {
+                let index = self.parse_binary(0)?;
+                self.expect("]")?;
+                expr = Expression::Index(Box::new(expr), Box::new(index));
+            } else if self.eat(".") {
+                expr = Expression::Member(Box::new(expr), self.parse_identifier()?);
+            } else if self.eat("->") {
+                expr = Expression::PointerMember(Box::new(expr), self.parse_identifier()?);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn parse_identifier(&mut self) -> Result<String, String> {
+        match self.peek().cloned() {
+            Some(Token::Identifier(name)) => {
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err("Expected a member name in expression".to_string()),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, String> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(Expression::Literal(value)),
+            Some(Token::Identifier(name)) => Ok(Expression::Variable(name)),
+            Some(Token::Register(name)) => Ok(Expression::Register(name)),
+            Some(Token::Punct("(")) => {
+                let expr = self.parse_binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            _ => Err("Expected a variable or an integer".to_string()),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            let value = if literal.starts_with("0x") || literal.starts_with("0X") {
+                i64::from_str_radix(&literal[2..], 16)
+            } else {
+                literal.parse()
+            };
+            tokens.push(Token::Number(
+                value.map_err(|_| format!("Invalid number \"{}\"", literal))?,
+            ));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Identifier(chars[start..i].iter().collect()));
+        } else if c == '$' {
+            let start = i + 1;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            if i == start {
+                return Err("Expected a register name after '$'".to_string());
+            }
+            tokens.push(Token::Register(chars[start..i].iter().collect()));
+        } else if c == '\'' {
+            // A character literal such as 'a'
+            match (chars.get(i + 1), chars.get(i + 2)) {
+                (Some(value), Some('\'')) => tokens.push(Token::Number(*value as i64)),
+                _ => return Err("Unmatched single quote.".to_string()),
+            }
+            i += 3;
+        } else {
+            let rest: String = chars[i..].iter().take(2).collect();
+            let punct = PUNCTUATION
+                .iter()
+                .find(|punct| rest.starts_with(*punct))
+                .ok_or_else(|| format!("Invalid character '{}' in expression", c))?;
+            tokens.push(Token::Punct(punct));
+            i += punct.len();
+        }
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Where the array `mem` lives in the made-up memory of the test context
+    const MEM_ADDR: usize = 0x1000;
+    /// The id of `int` in the made-up debugging information of the test context
+    const INT_ID: TypeId = 1;
+
+    /// Evaluates expressions over made-up variables, types and memory.
+    struct TestContext {
+        debug_data: DwarfData,
+        variables: HashMap<String, Value>,
+        memory: Vec<u8>,
+    }
+
+    impl Context for TestContext {
+        fn debug_data(&self) -> &DwarfData {
+            &self.debug_data
+        }
+
+        fn variable(&self, name: &str) -> Result<Value, String> {
+            self.variables
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("No symbol \"{}\" in current context.", name))
+        }
+
+        fn register(&self, name: &str) -> Result<Value, String> {
+            Err(format!("Invalid register `{}'", name))
+        }
+
+        fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+            addr.checked_sub(MEM_ADDR)
+                .and_then(|start| self.memory.get(start..start.checked_add(len)?))
+                .map(|bytes| bytes.to_vec())
+                .ok_or_else(|| format!("Cannot access memory at address {:#x}", addr))
+        }
+    }
+
+    fn int_array(values: &[i32], address: Option<usize>) -> Value {
+        Value {
+            value_type: Type::new(
+                format!("int [{}]", values.len()),
+                4 * values.len(),
+                TypeKind::Array {
+                    element: INT_ID,
+                    count: Some(values.len()),
+                },
+            ),
+            type_id: None,
+            bytes: values
+                .iter()
+                .flat_map(|value| value.to_le_bytes().to_vec())
+                .collect(),
+            address,
+        }
+    }
+
+    /// Sets up `x` (an int), `u` (an unsigned int), `d` (a double), `arr` (an int array held in
+    /// registers) and `mem` (an int array in memory).
+    fn context() -> TestContext {
+        let mut types = HashMap::new();
+        types.insert(
+            INT_ID,
+            Type::new("int".to_string(), 4, TypeKind::Base(Encoding::Signed)),
+        );
+        let mut variables = HashMap::new();
+        variables.insert("x".to_string(), Value::integer(6, 4, false));
+        variables.insert("u".to_string(), Value::integer(0, 4, true));
+        variables.insert("d".to_string(), Value::double(2.5));
+        variables.insert("arr".to_string(), int_array(&[10, 20, 30], None));
+        variables.insert("mem".to_string(), int_array(&[1, 2, 3, 4], Some(MEM_ADDR)));
+        let memory = [1i32, 2, 3, 4]
+            .iter()
+            .flat_map(|value| value.to_le_bytes().to_vec())
+            .collect();
+        TestContext {
+            debug_data: DwarfData::from_parts(Vec::new(), types),
+            variables,
+            memory,
+        }
+    }
+
+    /// Shows how an expression was grouped by the parser.
+    fn parsed(input: &str) -> String {
+        Expression::parse(input).unwrap().to_string()
+    }
+
+    fn eval(context: &TestContext, input: &str) -> Result<Scalar, String> {
+        Expression::parse(input)?
+            .evaluate(context)?
+            .scalar(&context.debug_data)
+    }
+
+    fn eval_int(context: &TestContext, input: &str) -> i64 {
+        match eval(context, input) {
+            Ok(Scalar::Signed(value)) => value,
+            Ok(Scalar::Unsigned(value)) => value as i64,
+            other => panic!("{} evaluated to {:?}", input, other),
+        }
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        assert_eq!(parsed("a + b * c - d"), "(a + (b * c)) - d");
+        assert_eq!(parsed("a || b && c | d"), "a || (b && (c | d))");
+        assert_eq!(parsed("1 << 2 + 3 < 4 == 1"), "((1 << (2 + 3)) < 4) == 1");
+        assert_eq!(parsed("a & b ^ c"), "(a & b) ^ c");
+        assert_eq!(parsed("(a + b) * c"), "(a + b) * c");
+    }
+
+    #[test]
+    fn test_parse_unary_and_postfix() {
+        assert_eq!(parsed("-x[1]"), "-x[1]");
+        assert_eq!(parsed("*p->next"), "*p->next");
+        assert_eq!(parsed("&s.member"), "&s.member");
+        assert_eq!(parsed("!~x"), "!(~x)");
+        assert_eq!(parsed("arr[i + 1][j]"), "arr[i + 1][j]");
+        assert_eq!(parsed("-5"), "-5");
+    }
+
+    #[test]
+    fn test_parse_casts() {
+        assert_eq!(parsed("(char *)p + 1"), "((char *)p) + 1");
+        assert_eq!(parsed("(unsigned long)x"), "(unsigned long)x");
+        assert_eq!(parsed("(struct node **)p"), "(struct node * *)p");
+        // A parenthesized variable is only a (typedef) cast if an operand follows
+        assert_eq!(parsed("(x)"), "x");
+        assert_eq!(parsed("(x) + 1"), "x + 1");
+        assert_eq!(parsed("(size_t)x"), "(size_t)x");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Expression::parse("1 +").is_err());
+        assert!(Expression::parse("(1").is_err());
+        assert!(Expression::parse("1 2").is_err());
+        assert!(Expression::parse("a[1").is_err());
+        assert!(Expression::parse("p->").is_err());
+        assert!(Expression::parse("$").is_err());
+        assert!(Expression::parse("x @ y").is_err());
+        assert!(Expression::parse("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn test_parse_literals() {
+        assert_eq!(Expression::parse("0x1f"), Ok(Expression::Literal(31)));
+        assert_eq!(Expression::parse("'a'"), Ok(Expression::Literal(97)));
+        assert_eq!(
+            Expression::parse("$rip"),
+            Ok(Expression::Register("rip".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_evaluate_arithmetic() {
+        let context = context();
+        assert_eq!(eval_int(&context, "1 + 2 * 3"), 7);
+        assert_eq!(eval_int(&context, "(1 + 2) * 3"), 9);
+        assert_eq!(eval_int(&context, "-7 / 2"), -3);
+        assert_eq!(eval_int(&context, "-7 % 3"), -1);
+        assert_eq!(eval_int(&context, "1 << 4 | 1"), 17);
+        assert_eq!(eval_int(&context, "~0"), -1);
+        assert_eq!(eval_int(&context, "x * x - 1"), 35);
+        assert_eq!(
+            eval(&context, "1 / 0").unwrap_err(),
+            "Division by zero".to_string()
+        );
+    }
+
+    #[test]
+    fn test_evaluate_logic() {
+        let context = context();
+        assert_eq!(eval_int(&context, "x > 5 && x < 10"), 1);
+        assert_eq!(eval_int(&context, "x == 5 || x != 6"), 0);
+        assert_eq!(eval_int(&context, "!x"), 0);
+        // The right-hand side isn't evaluated once the result is known
+        assert_eq!(eval_int(&context, "0 && 1 / 0"), 0);
+        assert_eq!(eval_int(&context, "1 || 1 / 0"), 1);
+    }
+
+    #[test]
+    fn test_evaluate_conversions() {
+        let context = context();
+        // An unsigned int operand makes the arithmetic (and comparison) unsigned
+        assert_eq!(eval_int(&context, "u - 1"), 0xffff_ffff);
+        assert_eq!(eval_int(&context, "u - 1 > 0"), 1);
+        assert_eq!(eval_int(&context, "(char)300"), 44);
+        assert_eq!(eval_int(&context, "(unsigned char)-1"), 255);
+        assert_eq!(eval_int(&context, "(int)d"), 2);
+        match eval(&context, "d * 2") {
+            Ok(Scalar::Float(value)) => assert_eq!(value, 5.0),
+            other => panic!("d * 2 evaluated to {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_index() {
+        let context = context();
+        assert_eq!(eval_int(&context, "arr[1]"), 20);
+        assert_eq!(eval_int(&context, "arr[x - 4]"), 30);
+        for input in &["arr[3]", "arr[-1]", "arr[0x7fffffffffffffff]"] {
+            assert_eq!(
+                eval(&context, input).unwrap_err(),
+                "No such array element.".to_string()
+            );
+        }
+        assert_eq!(eval_int(&context, "mem[2]"), 3);
+        assert_eq!(eval_int(&context, "*mem"), 1);
+        assert_eq!(eval_int(&context, "&mem[1]"), MEM_ADDR as i64 + 4);
+        assert_eq!(eval_int(&context, "*(mem + 3)"), 4);
+        assert!(eval(&context, "mem[-1]").is_err());
+        assert!(eval(&context, "mem[0x7fffffffffffffff]").is_err());
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        let context = context();
+        assert!(eval(&context, "nope + 1").is_err());
+        assert!(eval(&context, "*x").is_err());
+        assert!(eval(&context, "&arr").is_err());
+        assert!(eval(&context, "x.member").is_err());
+        assert!(eval(&context, "arr[d]").is_err());
+    }
+}
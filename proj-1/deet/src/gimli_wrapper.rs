@@ -5,18 +5,24 @@
 //!
 //! This code is a huge mess. Please don't read it unless you're trying to do an extension :)
 
-use gimli::{UnitOffset, UnitSectionOffset};
-use object::Object;
+use gimli::{UnitOffset, UnitSectionOffset, UnwindSection};
+use object::{Object, ObjectSection};
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    CfaRule, Encoding, File, Function, Line, Location, LocationListEntry, Member, Op, Qualifier,
+    RegisterRule, Type, TypeId, TypeKind, UnwindRow, Variable, MAX_TYPE_DEPTH,
+};
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
 use std::{io, path};
 
-pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
+pub fn load_file(
+    object: &object::File,
+    endian: gimli::RunTimeEndian,
+) -> Result<(Vec<File>, HashMap<TypeId, Type>), Error> {
     // Load a section and return as `Cow<[u8]>`.
     let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
         Ok(object
@@ -40,7 +46,7 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     let dwarf = dwarf_cow.borrow(&borrow_section);
 
     // Define a mapping from type offsets to type structs
-    let mut offset_to_type: HashMap<usize, Type> = HashMap::new();
+    let offset_to_type = load_types(&dwarf)?;
 
     let mut compilation_units: Vec<File> = Vec::new();
 
@@ -67,40 +73,23 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                     } else {
                         "<unknown>".to_string()
                     };
+                    let comp_dir = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_comp_dir) {
+                        if let Ok(DebugValue::Str(dir)) = get_attr_value(&attr, &unit, &dwarf) {
+                            dir
+                        } else {
+                            String::new()
+                        }
+                    } else {
+                        String::new()
+                    };
                     compilation_units.push(File {
                         name,
+                        comp_dir,
                         global_variables: Vec::new(),
                         functions: Vec::new(),
                         lines: Vec::new(),
                     });
                 }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
-                        }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
                 gimli::DW_TAG_subprogram => {
                     let mut func: Function = Default::default();
                     let mut attrs = entry.attrs();
@@ -129,6 +118,18 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                                     func.line_number = line_number.try_into().unwrap();
                                 }
                             }
+                            gimli::DW_AT_type => {
+                                if let Ok(DebugValue::Size(offset)) = val {
+                                    func.return_type = offset_to_type.get(&offset).cloned();
+                                }
+                            }
+                            gimli::DW_AT_frame_base => {
+                                if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
+                                    if let Ok(ops) = decode_expression(data, unit.encoding()) {
+                                        func.frame_base = ops;
+                                    }
+                                }
+                            }
                             _ => {}
                         }
                     }
@@ -136,9 +137,10 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                 }
                 gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
                     let mut name = String::new();
-                    let mut entity_type: Option<Type> = None;
+                    let mut entity_type: Option<(Type, TypeId)> = None;
                     let mut location: Option<Location> = None;
                     let mut line_number = 0;
+                    let mut declaration = false;
                     let mut attrs = entry.attrs();
                     while let Some(attr) = attrs.next()? {
                         let val = get_attr_value(&attr, &unit, &dwarf);
@@ -152,15 +154,33 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             gimli::DW_AT_type => {
                                 if let Ok(DebugValue::Size(offset)) = val {
                                     if let Some(dtype) = offset_to_type.get(&offset) {
-                                        entity_type = Some(dtype.clone());
+                                        entity_type = Some((dtype.clone(), offset));
                                     }
                                 }
                             }
                             gimli::DW_AT_location => {
-                                if let Some(loc) = get_location(&attr, &unit) {
+                                if let Some(loc) = get_location(&attr, &unit, &dwarf) {
                                     location = Some(loc);
                                 }
                             }
+                            // Variables whose value is known at compile time may not be stored
+                            // anywhere at all
+                            gimli::DW_AT_const_value if location.is_none() => {
+                                let bytes = match attr.value() {
+                                    gimli::AttributeValue::Block(data) => {
+                                        gimli::Reader::to_slice(&data)
+                                            .ok()
+                                            .map(|data| data.to_vec())
+                                    }
+                                    _ => constant_value(&attr)
+                                        .map(|value| (value as u64).to_le_bytes().to_vec()),
+                                };
+                                if let Some(bytes) = bytes {
+                                    location =
+                                        Some(Location::Expression(vec![Op::ImplicitValue(bytes)]));
+                                }
+                            }
+                            gimli::DW_AT_declaration => declaration = true,
                             gimli::DW_AT_decl_line => {
                                 if let Ok(DebugValue::Uint(num)) = val {
                                     line_number = num;
@@ -169,11 +189,15 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             _ => {}
                         }
                     }
-                    if let (Some(entity_type), Some(location)) = (entity_type, location) {
+                    // Variables without a location have been optimized out, but extern
+                    // declarations are defined (with a location) elsewhere
+                    let entity_type = entity_type.filter(|_| !declaration && !name.is_empty());
+                    if let Some((entity_type, type_id)) = entity_type {
                         let var = Variable {
                             name,
                             entity_type,
-                            location,
+                            type_id,
+                            location: location.unwrap_or(Location::OptimizedOut),
                             line_number: line_number.try_into().unwrap(),
                         };
                         match depth.cmp(&1) {
@@ -204,14 +228,18 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
             }
         }
 
-        // Get line numbers
-        if let Some(program) = unit.line_program.clone() {
+        // Get line numbers. The unit's DIEs were read above, so it is the last File; only
+        // statements of its main source file are kept (not code inlined from headers).
+        let file = compilation_units.last_mut();
+        if let (Some(program), Some(file)) = (unit.line_program.clone(), file) {
+            let unit_path = path::Path::new(&file.comp_dir).join(&file.name);
             // Iterate over the line program rows.
             let mut rows = program.rows();
             while let Some((header, row)) = rows.next_row()? {
-                if !row.end_sequence() {
+                if !row.end_sequence() && row.is_stmt() {
                     // Determine the path. Real applications should cache this for performance.
-                    let mut path = path::PathBuf::new();
+                    // Relative paths are relative to the compilation directory.
+                    let mut path = path::PathBuf::from(&file.comp_dir);
                     if let Some(file) = row.file(header) {
                         if let Some(dir) = file.directory(header) {
                             path.push(dwarf.attr_string(&unit, dir)?.to_string_lossy().as_ref());
@@ -224,16 +252,11 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                         );
                     }
 
-                    // Get the File
-                    let file = compilation_units
-                        .iter_mut()
-                        .find(|f| f.name == path.as_os_str().to_str().unwrap());
-
                     // Determine line/column. DWARF line/column is never 0, so we use that
                     // but other applications may want to display this differently.
                     let line = row.line().unwrap_or(0);
 
-                    if let Some(file) = file {
+                    if path == unit_path {
                         file.lines.push(Line {
                             file: file.name.clone(),
                             number: line.try_into().unwrap(),
@@ -244,7 +267,304 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
             }
         }
     }
-    Ok(compilation_units)
+    Ok((compilation_units, offset_to_type))
+}
+
+/// Collects every type in the debugging information, keyed by the offset of its entry in
+/// .debug_info (which is how other entries refer to it). This is done in a separate pass over the
+/// units so that types can refer to types defined after them, like a struct holding a pointer to
+/// itself.
+fn load_types<R: Reader>(dwarf: &gimli::Dwarf<R>) -> Result<HashMap<TypeId, Type>, Error> {
+    let mut types: HashMap<TypeId, Type> = HashMap::new();
+
+    let mut iter = dwarf.units();
+    while let Some(header) = iter.next()? {
+        let unit = dwarf.unit(header)?;
+
+        // The types whose children (members, enumerators or array dimensions) we are iterating
+        // over, along with their depth
+        let mut parents: Vec<(isize, TypeId)> = Vec::new();
+        // The innermost dimension of the array we are iterating over
+        let mut last_dimension: Option<TypeId> = None;
+        let mut depth = 0;
+        let mut entries = unit.entries();
+        while let Some((delta_depth, entry)) = entries.next_dfs()? {
+            depth += delta_depth;
+            while parents
+                .last()
+                .is_some_and(|(parent_depth, _)| *parent_depth >= depth)
+            {
+                parents.pop();
+            }
+            let parent = parents
+                .last()
+                .filter(|(parent_depth, _)| *parent_depth == depth - 1)
+                .map(|(_, parent)| *parent);
+
+            let offset = match entry.offset().to_unit_section_offset(&unit) {
+                UnitSectionOffset::DebugInfoOffset(offset) => offset.0,
+                UnitSectionOffset::DebugTypesOffset(offset) => offset.0,
+            };
+            let name = match entry.attr(gimli::DW_AT_name)? {
+                Some(attr) => match get_attr_value(&attr, &unit, dwarf) {
+                    Ok(DebugValue::Str(name)) => name,
+                    _ => String::new(),
+                },
+                None => String::new(),
+            };
+            let size = entry
+                .attr(gimli::DW_AT_byte_size)?
+                .and_then(|attr| attr.udata_value())
+                .unwrap_or(0) as usize;
+            let target = match entry.attr(gimli::DW_AT_type)? {
+                Some(attr) => match get_attr_value(&attr, &unit, dwarf) {
+                    Ok(DebugValue::Size(offset)) => Some(offset),
+                    _ => None,
+                },
+                None => None,
+            };
+
+            let kind = match entry.tag() {
+                gimli::DW_TAG_base_type => {
+                    let encoding = match entry.attr_value(gimli::DW_AT_encoding) {
+                        Ok(Some(gimli::AttributeValue::Encoding(encoding))) => match encoding {
+                            gimli::DW_ATE_signed => Encoding::Signed,
+                            gimli::DW_ATE_unsigned => Encoding::Unsigned,
+                            gimli::DW_ATE_signed_char => Encoding::SignedChar,
+                            gimli::DW_ATE_unsigned_char => Encoding::UnsignedChar,
+                            gimli::DW_ATE_float => Encoding::Float,
+                            gimli::DW_ATE_boolean => Encoding::Boolean,
+                            _ => Encoding::Unknown,
+                        },
+                        _ => Encoding::Unknown,
+                    };
+                    TypeKind::Base(encoding)
+                }
+                gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type => {
+                    TypeKind::Pointer(target)
+                }
+                gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type => {
+                    TypeKind::Struct(Vec::new())
+                }
+                gimli::DW_TAG_union_type => TypeKind::Union(Vec::new()),
+                gimli::DW_TAG_enumeration_type => TypeKind::Enum(Vec::new()),
+                gimli::DW_TAG_array_type => match target {
+                    Some(element) => {
+                        last_dimension = None;
+                        TypeKind::Array {
+                            element,
+                            count: None,
+                        }
+                    }
+                    None => continue,
+                },
+                gimli::DW_TAG_typedef => TypeKind::Typedef(target),
+                gimli::DW_TAG_const_type => TypeKind::Qualified(Qualifier::Const, target),
+                gimli::DW_TAG_volatile_type => TypeKind::Qualified(Qualifier::Volatile, target),
+                gimli::DW_TAG_restrict_type => TypeKind::Qualified(Qualifier::Restrict, target),
+                gimli::DW_TAG_atomic_type => TypeKind::Qualified(Qualifier::Atomic, target),
+                gimli::DW_TAG_subroutine_type => TypeKind::Function,
+                gimli::DW_TAG_member => {
+                    let mut offset = entry
+                        .attr(gimli::DW_AT_data_member_location)?
+                        .and_then(|attr| attr.udata_value())
+                        .unwrap_or(0) as usize;
+                    let bit_size = entry
+                        .attr(gimli::DW_AT_bit_size)?
+                        .and_then(|attr| attr.udata_value())
+                        .map(|bits| bits as usize);
+                    let mut bit_offset = 0;
+                    if let Some(bits) = bit_size {
+                        let data_bit_offset = entry
+                            .attr(gimli::DW_AT_data_bit_offset)?
+                            .and_then(|attr| attr.udata_value());
+                        let msb_offset = entry
+                            .attr(gimli::DW_AT_bit_offset)?
+                            .and_then(|attr| attr.udata_value());
+                        let first_bit = match (data_bit_offset, msb_offset) {
+                            (Some(first_bit), _) => Some(first_bit as usize),
+                            // DWARF 2 and 3 count from the most significant bit of a storage unit
+                            // of DW_AT_byte_size bytes at DW_AT_data_member_location
+                            (None, Some(msb_offset)) => {
+                                (8 * (offset + size)).checked_sub(msb_offset as usize + bits)
+                            }
+                            (None, None) => None,
+                        };
+                        if let Some(first_bit) = first_bit {
+                            offset = first_bit / 8;
+                            bit_offset = first_bit % 8;
+                        }
+                    }
+                    let member_of = parent.and_then(|parent| types.get_mut(&parent));
+                    if let (Some(parent), Some(type_id)) = (member_of, target) {
+                        if let TypeKind::Struct(members) | TypeKind::Union(members) =
+                            &mut parent.kind
+                        {
+                            members.push(Member {
+                                name,
+                                offset,
+                                type_id,
+                                bit_size,
+                                bit_offset,
+                            });
+                        }
+                    }
+                    continue;
+                }
+                gimli::DW_TAG_enumerator => {
+                    let value = entry
+                        .attr(gimli::DW_AT_const_value)?
+                        .and_then(|attr| constant_value(&attr));
+                    let enumerator_of = parent.and_then(|parent| types.get_mut(&parent));
+                    if let (Some(parent), Some(value)) = (enumerator_of, value) {
+                        if let TypeKind::Enum(enumerators) = &mut parent.kind {
+                            enumerators.push((name, value));
+                        }
+                    }
+                    continue;
+                }
+                gimli::DW_TAG_subrange_type => {
+                    let count = match entry.attr(gimli::DW_AT_count)? {
+                        Some(attr) => attr.udata_value(),
+                        None => entry
+                            .attr(gimli::DW_AT_upper_bound)?
+                            .and_then(|attr| attr.udata_value())
+                            .map(|upper_bound| upper_bound + 1),
+                    }
+                    .map(|count| count as usize);
+                    let array = match parent {
+                        Some(array) => array,
+                        None => continue,
+                    };
+                    match last_dimension {
+                        // The first dimension belongs to the array type itself
+                        None => {
+                            if let Some(Type {
+                                kind: TypeKind::Array { count: c, .. },
+                                ..
+                            }) = types.get_mut(&array)
+                            {
+                                *c = count;
+                            }
+                            last_dimension = Some(array);
+                        }
+                        // Further dimensions make the elements arrays themselves, for which we
+                        // make up types identified by the subrange entries
+                        Some(outer) => {
+                            if let Some(Type {
+                                kind: TypeKind::Array { element, .. },
+                                ..
+                            }) = types.get_mut(&outer)
+                            {
+                                let inner = TypeKind::Array {
+                                    element: *element,
+                                    count,
+                                };
+                                *element = offset;
+                                types.insert(offset, Type::new(String::new(), 0, inner));
+                            }
+                            last_dimension = Some(offset);
+                        }
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            if entry.has_children() {
+                parents.push((depth, offset));
+            }
+            types.insert(offset, Type::new(name, size, kind));
+        }
+    }
+
+    // Work out the sizes and names of types that derive them from other types
+    let derived: Vec<(TypeId, usize, String)> = types
+        .keys()
+        .map(|id| {
+            (
+                *id,
+                type_size(&types, Some(*id), 0),
+                type_name(&types, Some(*id), 0),
+            )
+        })
+        .collect();
+    for (id, size, name) in derived {
+        let entity_type = types.get_mut(&id).unwrap();
+        entity_type.size = size;
+        entity_type.name = name;
+    }
+    Ok(types)
+}
+
+fn type_size(types: &HashMap<TypeId, Type>, id: Option<TypeId>, depth: usize) -> usize {
+    let entity_type = match id.and_then(|id| types.get(&id)) {
+        Some(entity_type) if depth < MAX_TYPE_DEPTH => entity_type,
+        _ => return 0,
+    };
+    match entity_type.kind {
+        TypeKind::Pointer(_) if entity_type.size == 0 => std::mem::size_of::<usize>(),
+        TypeKind::Array { element, count } if entity_type.size == 0 => {
+            count.unwrap_or(0) * type_size(types, Some(element), depth + 1)
+        }
+        TypeKind::Typedef(target) | TypeKind::Qualified(_, target) => {
+            type_size(types, target, depth + 1)
+        }
+        _ => entity_type.size,
+    }
+}
+
+/// Renders the name of a type the way it would be written in C (give or take).
+fn type_name(types: &HashMap<TypeId, Type>, id: Option<TypeId>, depth: usize) -> String {
+    let entity_type = match id {
+        Some(id) => match types.get(&id) {
+            Some(entity_type) if depth < MAX_TYPE_DEPTH => entity_type,
+            _ => return "?".to_string(),
+        },
+        None => return "void".to_string(),
+    };
+    let tag = |keyword: &str| {
+        if entity_type.name.is_empty() {
+            format!("{} {{...}}", keyword)
+        } else {
+            format!("{} {}", keyword, entity_type.name)
+        }
+    };
+    match &entity_type.kind {
+        TypeKind::Base(_) | TypeKind::Typedef(_) => entity_type.name.clone(),
+        TypeKind::Pointer(target) => format!("{} *", type_name(types, *target, depth + 1)),
+        TypeKind::Struct(_) => tag("struct"),
+        TypeKind::Union(_) => tag("union"),
+        TypeKind::Enum(_) => tag("enum"),
+        TypeKind::Array { .. } => {
+            // int[2][3] is an array of two arrays of three ints. An array can't contain
+            // itself, but malformed debug info may say so.
+            let mut dimensions = String::new();
+            let mut element = id.unwrap();
+            for _ in 0..MAX_TYPE_DEPTH {
+                let (inner, count) = match types.get(&element).map(|inner| &inner.kind) {
+                    Some(TypeKind::Array {
+                        element: inner,
+                        count,
+                    }) => (*inner, *count),
+                    _ => break,
+                };
+                match count {
+                    Some(count) => dimensions.push_str(&format!("[{}]", count)),
+                    None => dimensions.push_str("[]"),
+                }
+                element = inner;
+            }
+            format!(
+                "{} {}",
+                type_name(types, Some(element), depth + 1),
+                dimensions
+            )
+        }
+        TypeKind::Qualified(qualifier, target) => {
+            format!("{} {}", qualifier, type_name(types, *target, depth + 1))
+        }
+        TypeKind::Function => "<function>".to_string(),
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -256,6 +576,93 @@ pub enum DebugValue {
     NoVal,
 }
 
+/// The call frame information (.eh_frame and .debug_frame sections) of an executable. The section
+/// contents are copied out of the file so that unwind rules can be looked up at any time while
+/// debugging.
+#[derive(Default)]
+pub struct CallFrameInfo {
+    endian: gimli::RunTimeEndian,
+    eh_frame: Vec<u8>,
+    eh_frame_address: u64,
+    debug_frame: Vec<u8>,
+    text_address: u64,
+}
+
+impl CallFrameInfo {
+    pub fn load(object: &object::File, endian: gimli::RunTimeEndian) -> CallFrameInfo {
+        let section_data = |name| {
+            object
+                .section_data_by_name(name)
+                .map(|data| data.into_owned())
+                .unwrap_or_default()
+        };
+        let section_address = |name| {
+            object
+                .section_by_name(name)
+                .map(|section| section.address())
+                .unwrap_or(0)
+        };
+        CallFrameInfo {
+            endian,
+            eh_frame: section_data(".eh_frame"),
+            eh_frame_address: section_address(".eh_frame"),
+            debug_frame: section_data(".debug_frame"),
+            text_address: section_address(".text"),
+        }
+    }
+
+    /// Returns the unwind rules in effect at the given address, preferring .eh_frame over
+    /// .debug_frame. Returns None if neither covers the address, or if the CFA is computed by a
+    /// DWARF expression (as it is in the PLT).
+    pub fn unwind_row(&self, address: usize) -> Option<UnwindRow> {
+        let bases = gimli::BaseAddresses::default()
+            .set_eh_frame(self.eh_frame_address)
+            .set_text(self.text_address);
+        let mut ctx = gimli::UninitializedUnwindContext::new();
+        let eh_frame = gimli::EhFrame::new(&self.eh_frame, self.endian);
+        let row = match eh_frame.unwind_info_for_address(
+            &bases,
+            &mut ctx,
+            address as u64,
+            gimli::EhFrame::cie_from_offset,
+        ) {
+            Ok(row) => row,
+            Err(_) => gimli::DebugFrame::new(&self.debug_frame, self.endian)
+                .unwind_info_for_address(
+                    &bases,
+                    &mut ctx,
+                    address as u64,
+                    gimli::DebugFrame::cie_from_offset,
+                )
+                .ok()?,
+        };
+
+        let cfa = match *row.cfa() {
+            gimli::CfaRule::RegisterAndOffset { register, offset } => CfaRule {
+                register: register.0,
+                offset,
+            },
+            gimli::CfaRule::Expression(_) => return None,
+        };
+        let registers = row
+            .registers()
+            .map(|(register, rule)| {
+                let rule = match *rule {
+                    gimli::RegisterRule::SameValue => RegisterRule::SameValue,
+                    gimli::RegisterRule::Offset(offset) => RegisterRule::Offset(offset),
+                    gimli::RegisterRule::ValOffset(offset) => RegisterRule::ValOffset(offset),
+                    gimli::RegisterRule::Register(other) => RegisterRule::Register(other.0),
+                    // Expression rules are rare outside of hand-written assembly; treat the
+                    // register as lost rather than guessing.
+                    _ => RegisterRule::Undefined,
+                };
+                (register.0, rule)
+            })
+            .collect();
+        Some(UnwindRow { cfa, registers })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
     Gimli(gimli::Error),
@@ -295,25 +702,144 @@ impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
 
 trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
 
-fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> Option<Location> {
-    if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
-        let encoding = unit.encoding();
-        let mut pc = data.0.clone();
-        if pc.len() > 0 {
-            if let Ok(op) = gimli::Operation::parse(&mut pc, encoding) {
-                match op {
-                    gimli::Operation::FrameOffset { offset } => {
-                        return Some(Location::FramePointerOffset(offset.try_into().unwrap()));
-                    }
-                    gimli::Operation::Address { address } => {
-                        return Some(Location::Address(address.try_into().unwrap()));
-                    }
-                    _ => {}
-                }
+/// Decodes an integer constant (DW_AT_const_value). The fixed-size data forms don't say whether
+/// they are signed; compilers use DW_FORM_sdata for negative values, so those are zero-extended.
+fn constant_value<R: Reader>(attr: &gimli::Attribute<R>) -> Option<i64> {
+    match attr.value() {
+        gimli::AttributeValue::Sdata(value) => Some(value),
+        _ => attr.udata_value().map(|value| value as i64),
+    }
+}
+
+fn get_location<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<Location> {
+    match attr.value() {
+        gimli::AttributeValue::Exprloc(ref data) => {
+            let ops = decode_expression(data, unit.encoding()).ok()?;
+            Some(match ops.as_slice() {
+                [Op::Address(address)] => Location::Address(*address as usize),
+                [Op::FrameOffset(offset)] => Location::FramePointerOffset(*offset as isize),
+                _ => Location::Expression(ops),
+            })
+        }
+        value => {
+            let mut locations = dwarf.attr_locations(unit, value).ok()??;
+            let mut entries = Vec::new();
+            while let Ok(Some(entry)) = locations.next() {
+                // An entry we can't decode only affects its own range
+                let expression = decode_expression(&entry.data, unit.encoding())
+                    .unwrap_or_else(|_| vec![Op::Unsupported("malformed expression".to_string())]);
+                entries.push(LocationListEntry {
+                    begin: entry.range.begin as usize,
+                    end: entry.range.end as usize,
+                    expression,
+                });
             }
+            Some(Location::List(entries))
         }
     }
-    None
+}
+
+/// Decodes a DWARF expression into our own representation of its operations, so that it can be
+/// evaluated while debugging. Operations we don't know how to evaluate are kept as
+/// `Op::Unsupported`.
+fn decode_expression<R: Reader>(
+    expression: &gimli::Expression<R>,
+    encoding: gimli::Encoding,
+) -> Result<Vec<Op>, Error> {
+    let len = expression.0.len();
+    let mut pc = expression.0.clone();
+    let mut ops = Vec::new();
+    // The byte offset of each operation, and the byte offsets that branches jump to
+    let mut offsets = Vec::new();
+    let mut branches: Vec<(usize, isize)> = Vec::new();
+    while pc.len() != 0 {
+        offsets.push(len - pc.len());
+        let mut op_pc = pc.clone();
+        let dwop = gimli::DwOp(op_pc.read_u8()?);
+        let op = gimli::Operation::parse(&mut pc, encoding)?;
+        let next = (len - pc.len()) as isize;
+        let op = match op {
+            gimli::Operation::Address { address } => Op::Address(address),
+            gimli::Operation::UnsignedConstant { value } => Op::Constant(value),
+            gimli::Operation::SignedConstant { value } => Op::Constant(value as u64),
+            gimli::Operation::Register { register } => Op::Register(register.0),
+            gimli::Operation::RegisterOffset {
+                register,
+                offset,
+                base_type: UnitOffset(0),
+            } => Op::RegisterOffset(register.0, offset),
+            gimli::Operation::FrameOffset { offset } => Op::FrameOffset(offset),
+            gimli::Operation::CallFrameCFA => Op::CallFrameCfa,
+            gimli::Operation::Deref {
+                base_type: UnitOffset(0),
+                size,
+                space: false,
+            } => Op::Deref(size),
+            gimli::Operation::Pick { index } => Op::Pick(index),
+            gimli::Operation::Drop => Op::Drop,
+            gimli::Operation::Swap => Op::Swap,
+            gimli::Operation::Rot => Op::Rot,
+            gimli::Operation::Abs => Op::Abs,
+            gimli::Operation::And => Op::And,
+            gimli::Operation::Div => Op::Div,
+            gimli::Operation::Minus => Op::Minus,
+            gimli::Operation::Mod => Op::Mod,
+            gimli::Operation::Mul => Op::Mul,
+            gimli::Operation::Neg => Op::Neg,
+            gimli::Operation::Not => Op::Not,
+            gimli::Operation::Or => Op::Or,
+            gimli::Operation::Plus => Op::Plus,
+            gimli::Operation::PlusConstant { value } => Op::PlusConstant(value),
+            gimli::Operation::Shl => Op::Shl,
+            gimli::Operation::Shr => Op::Shr,
+            gimli::Operation::Shra => Op::Shra,
+            gimli::Operation::Xor => Op::Xor,
+            gimli::Operation::Eq => Op::Eq,
+            gimli::Operation::Ge => Op::Ge,
+            gimli::Operation::Gt => Op::Gt,
+            gimli::Operation::Le => Op::Le,
+            gimli::Operation::Lt => Op::Lt,
+            gimli::Operation::Ne => Op::Ne,
+            gimli::Operation::Bra { target } => {
+                branches.push((ops.len(), next + target as isize));
+                Op::Bra(0)
+            }
+            gimli::Operation::Skip { target } => {
+                branches.push((ops.len(), next + target as isize));
+                Op::Skip(0)
+            }
+            gimli::Operation::Nop => Op::Nop,
+            gimli::Operation::Piece {
+                size_in_bits,
+                bit_offset: None,
+            } if size_in_bits % 8 == 0 => Op::Piece((size_in_bits / 8) as usize),
+            gimli::Operation::StackValue => Op::StackValue,
+            gimli::Operation::ImplicitValue { data } => {
+                Op::ImplicitValue(data.to_slice()?.to_vec())
+            }
+            _ => Op::Unsupported(dwop.to_string()),
+        };
+        ops.push(op);
+    }
+
+    for (index, target) in branches {
+        // Branching to the very end of the expression is how it terminates early
+        let target = if target == len as isize {
+            Some(ops.len())
+        } else {
+            offsets.iter().position(|offset| *offset as isize == target)
+        };
+        ops[index] = match (&ops[index], target) {
+            (Op::Bra(_), Some(target)) => Op::Bra(target),
+            (Op::Skip(_), Some(target)) => Op::Skip(target),
+            _ => Op::Unsupported("DW_OP_bra".to_string()),
+        };
+    }
+    Ok(ops)
 }
 
 // based on dwarf_dump.rs
@@ -342,6 +868,16 @@ fn get_attr_value<R: Reader>(
                 Ok(DebugValue::Str(format!("<.debug_str+0x{:08x}>", offset.0)))
             }
         }
+        gimli::AttributeValue::DebugLineStrRef(offset) => {
+            if let Ok(s) = dwarf.debug_line_str.get_str(offset) {
+                Ok(DebugValue::Str(format!("{}", s.to_string_lossy()?)))
+            } else {
+                Ok(DebugValue::Str(format!(
+                    "<.debug_line_str+0x{:08x}>",
+                    offset.0
+                )))
+            }
+        }
         gimli::AttributeValue::Sdata(data) => Ok(DebugValue::Int(data)),
         gimli::AttributeValue::Addr(data) => Ok(DebugValue::Uint(data)),
         gimli::AttributeValue::Udata(data) => Ok(DebugValue::Uint(data)),
@@ -608,3 +1144,91 @@ fn dump_op<R: Reader, W: Write>(
     };
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Result<Vec<Op>, Error> {
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 4,
+            address_size: 8,
+        };
+        let expression = gimli::Expression(gimli::EndianSlice::new(bytes, gimli::LittleEndian));
+        decode_expression(&expression, encoding)
+    }
+
+    #[test]
+    fn test_decode_locations() {
+        // DW_OP_fbreg -20
+        assert_eq!(decode(&[0x91, 0x6c]).unwrap(), vec![Op::FrameOffset(-20)]);
+        // DW_OP_addr 0x404028
+        assert_eq!(
+            decode(&[0x03, 0x28, 0x40, 0x40, 0, 0, 0, 0, 0]).unwrap(),
+            vec![Op::Address(0x404028)]
+        );
+        // DW_OP_breg6 16; DW_OP_deref
+        assert_eq!(
+            decode(&[0x76, 0x10, 0x06]).unwrap(),
+            vec![Op::RegisterOffset(6, 16), Op::Deref(8)]
+        );
+        // DW_OP_reg0; DW_OP_piece 4; DW_OP_reg1; DW_OP_piece 4
+        assert_eq!(
+            decode(&[0x50, 0x93, 0x04, 0x51, 0x93, 0x04]).unwrap(),
+            vec![Op::Register(0), Op::Piece(4), Op::Register(1), Op::Piece(4)]
+        );
+    }
+
+    #[test]
+    fn test_decode_values() {
+        // DW_OP_lit5; DW_OP_consts -3; DW_OP_plus; DW_OP_stack_value
+        assert_eq!(
+            decode(&[0x35, 0x11, 0x7d, 0x22, 0x9f]).unwrap(),
+            vec![
+                Op::Constant(5),
+                Op::Constant(-3i64 as u64),
+                Op::Plus,
+                Op::StackValue
+            ]
+        );
+        // DW_OP_implicit_value 2 0xab 0xcd
+        assert_eq!(
+            decode(&[0x9e, 0x02, 0xab, 0xcd]).unwrap(),
+            vec![Op::ImplicitValue(vec![0xab, 0xcd])]
+        );
+    }
+
+    #[test]
+    fn test_decode_branches() {
+        // 0: DW_OP_lit1; 1: DW_OP_bra +4; 4: DW_OP_lit2; 5: DW_OP_skip +1; 8: DW_OP_lit3
+        // Branch targets become operation indices, with the end of the expression one past the
+        // last operation
+        assert_eq!(
+            decode(&[0x31, 0x28, 0x04, 0x00, 0x32, 0x2f, 0x01, 0x00, 0x33]).unwrap(),
+            vec![
+                Op::Constant(1),
+                Op::Bra(4),
+                Op::Constant(2),
+                Op::Skip(5),
+                Op::Constant(3)
+            ]
+        );
+        // A branch into the middle of an operation can't be followed
+        assert_eq!(
+            decode(&[0x31, 0x28, 0x02, 0x00, 0x32, 0x2f, 0x01, 0x00, 0x33]).unwrap()[1],
+            Op::Unsupported("DW_OP_bra".to_string())
+        );
+    }
+
+    #[test]
+    fn test_decode_unsupported() {
+        // DW_OP_push_object_address is kept by name
+        assert_eq!(
+            decode(&[0x97]).unwrap(),
+            vec![Op::Unsupported("DW_OP_push_object_address".to_string())]
+        );
+        // A truncated DW_OP_fbreg
+        assert!(decode(&[0x91]).is_err());
+    }
+}
@@ -1,17 +1,97 @@
-use crate::dwarf_data::DwarfData;
+use crate::dwarf_data::{
+    DwarfData, Encoding, FrameTable, Location, Op, RegisterRule, Type, TypeKind, UnwindRow,
+    Variable,
+};
 use nix::sys::ptrace;
 use nix::sys::signal;
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
-use nix::unistd::Pid;
-use std::collections::HashMap;
+use nix::unistd::{self, Pid};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::io::Read;
 use std::mem::size_of;
 use std::os::unix::process::CommandExt;
-use std::process::{Child, Command};
+use std::process::Command;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// offsetof(struct user, u_debugreg) on x86-64, used to access the debug registers through
+/// PTRACE_PEEKUSER/PTRACE_POKEUSER.
+const DEBUG_REGISTERS_OFFSET: usize = 848;
 
 fn align_addr_to_word(addr: usize) -> usize {
     addr & (-(size_of::<usize>() as isize) as usize)
 }
 
+/// Returns the proper name of a register, translating gdb's $pc, $sp and $fp aliases.
+fn canonical_register(name: &str) -> &str {
+    match name {
+        "pc" => "rip",
+        "sp" => "rsp",
+        "fp" => "rbp",
+        name => name,
+    }
+}
+
+/// The registers `info registers` shows, in the order it shows them.
+pub const REGISTER_NAMES: [&str; 26] = [
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "rip", "eflags", "cs", "ss", "ds", "es", "fs", "gs", "fs_base", "gs_base",
+];
+
+/// Returns the field of `regs` holding the named register.
+fn register_field<'a>(regs: &'a mut libc::user_regs_struct, name: &str) -> Option<&'a mut u64> {
+    Some(match canonical_register(name) {
+        "rax" => &mut regs.rax,
+        "rbx" => &mut regs.rbx,
+        "rcx" => &mut regs.rcx,
+        "rdx" => &mut regs.rdx,
+        "rsi" => &mut regs.rsi,
+        "rdi" => &mut regs.rdi,
+        "rbp" => &mut regs.rbp,
+        "rsp" => &mut regs.rsp,
+        "r8" => &mut regs.r8,
+        "r9" => &mut regs.r9,
+        "r10" => &mut regs.r10,
+        "r11" => &mut regs.r11,
+        "r12" => &mut regs.r12,
+        "r13" => &mut regs.r13,
+        "r14" => &mut regs.r14,
+        "r15" => &mut regs.r15,
+        "rip" => &mut regs.rip,
+        "eflags" => &mut regs.eflags,
+        "cs" => &mut regs.cs,
+        "ss" => &mut regs.ss,
+        "ds" => &mut regs.ds,
+        "es" => &mut regs.es,
+        "fs" => &mut regs.fs,
+        "gs" => &mut regs.gs,
+        "fs_base" => &mut regs.fs_base,
+        "gs_base" => &mut regs.gs_base,
+        _ => return None,
+    })
+}
+
+/// Which process to keep debugging when the inferior forks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FollowForkMode {
+    Parent,
+    Child,
+}
+
+/// The ptrace options every traced process and thread gets: report new threads and processes,
+/// the end of a vfork, and execs.
+fn trace_options() -> ptrace::Options {
+    ptrace::Options::PTRACE_O_TRACECLONE
+        | ptrace::Options::PTRACE_O_TRACEFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORKDONE
+        | ptrace::Options::PTRACE_O_TRACEEXEC
+}
+
+#[derive(Clone, Copy)]
 pub enum Status {
     /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
     /// current instruction pointer that it is stopped at.
@@ -23,44 +103,765 @@ pub enum Status {
     /// Indicates the inferior exited due to a signal. Contains the signal that killed the
     /// process.
     Signaled(signal::Signal),
+
+    /// Indicates the inferior replaced its program with exec. It is stopped at the start of the
+    /// new program, which none of our breakpoints have been inserted into yet.
+    Exec,
+}
+
+/// Tells operations that run the inferior for a while (continuing, stepping over a line or
+/// running until a function returns) what to make of a stop along the way: Some(true) if it is to
+/// be reported, Some(false) if the operation should carry on (such as at a breakpoint whose
+/// condition doesn't hold), or None if the stop is not at a breakpoint or watchpoint and isn't
+/// caused by a signal.
+pub type StopFilter<'a> = dyn FnMut(&Inferior, Status) -> Option<bool> + 'a;
+
+/// Why the value of a variable could not be read.
+#[derive(Debug)]
+pub enum ReadError {
+    /// The compiler didn't keep the variable anywhere at this point in the program.
+    OptimizedOut,
+    /// The variable isn't stored in (contiguous) memory, so it has no address.
+    NotInMemory,
+    /// The variable's location uses a DWARF operation or register we can't evaluate.
+    Unsupported(String),
+    /// The variable's location expression is malformed.
+    InvalidExpression,
+    /// There is no register by that name.
+    UnknownRegister(String),
+    /// Reading registers or memory from the inferior failed.
+    Ptrace(nix::Error),
+}
+
+impl From<nix::Error> for ReadError {
+    fn from(err: nix::Error) -> Self {
+        ReadError::Ptrace(err)
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::OptimizedOut => write!(f, "value has been optimized out"),
+            ReadError::NotInMemory => write!(f, "value is not stored in memory"),
+            ReadError::Unsupported(what) => write!(f, "unhandled DWARF location {}", what),
+            ReadError::InvalidExpression => write!(f, "invalid DWARF location expression"),
+            ReadError::UnknownRegister(name) => write!(f, "Invalid register `{}'", name),
+            ReadError::Ptrace(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Where a piece of a variable lives, as described by a DWARF location expression.
+#[derive(Debug, Clone)]
+enum Piece {
+    Memory(usize),
+    Register(u16),
+    /// The value itself (DW_OP_stack_value) rather than where it is stored.
+    Value(u64),
+    /// The bytes of the value itself (DW_OP_implicit_value).
+    Bytes(Vec<u8>),
+    OptimizedOut,
+}
+
+/// DWARF numbers of the x86-64 registers that matter for unwinding. Registers 0 through 16 are rax,
+/// rdx, rcx, rbx, rsi, rdi, rbp, rsp, r8 through r15, and the return address (rip).
+const DWARF_RBP: usize = 6;
+const DWARF_RSP: usize = 7;
+const DWARF_RA: usize = 16;
+const DWARF_REGISTER_COUNT: usize = 17;
+/// Names of the registers above by DWARF register number, with rip in place of the return address.
+const DWARF_REGISTER_NAMES: [&str; DWARF_REGISTER_COUNT] = [
+    "rax", "rdx", "rcx", "rbx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "rip",
+];
+/// DWARF numbers of xmm0 through xmm15.
+const DWARF_XMM0: u16 = 17;
+const DWARF_XMM15: u16 = 32;
+/// Registers a callee must preserve (rbx, rbp, r12 through r15), and which therefore keep their
+/// value across a call unless the call frame information says otherwise.
+const CALLEE_SAVED_REGISTERS: [usize; 6] = [3, 6, 12, 13, 14, 15];
+/// Give up on call stacks deeper than this, in case the stack is corrupted into a cycle.
+const MAX_FRAMES: usize = 4096;
+/// Give up on DWARF location expressions that take more operations than this to evaluate.
+const MAX_EXPRESSION_STEPS: usize = 10_000;
+/// Binaries are mapped in whole pages, so the first one is loaded at the start of a page.
+const PAGE_SIZE: usize = 4096;
+
+/// A frame of the call stack, as recovered by `Inferior::unwind`.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// How many calls away from the innermost frame (level 0) this frame is.
+    pub level: usize,
+    /// The (runtime) address of the instruction being executed in this frame. For every frame but
+    /// the innermost one, this is the return address of the call into the next frame.
+    pub pc: usize,
+    /// The canonical frame address: the value of rsp just before the call that created this frame.
+    pub cfa: usize,
+    /// Register values in this frame by DWARF register number, or None if they couldn't be
+    /// recovered.
+    pub registers: [Option<u64>; DWARF_REGISTER_COUNT],
+}
+
+impl Frame {
+    /// Returns the (runtime) address to look up this frame's function and line by. Return
+    /// addresses point after the call instruction, which may belong to the next line (or even the
+    /// next function), so caller frames are looked up by the call itself.
+    pub fn lookup_pc(&self) -> usize {
+        if self.level == 0 {
+            self.pc
+        } else {
+            self.pc - 1
+        }
+    }
+}
+
+/// Details of the last signal a thread received, as reported by PTRACE_GETSIGINFO.
+#[derive(Debug, Clone, Copy)]
+pub struct SignalInfo {
+    /// The si_code of the signal: why it was raised (e.g. SEGV_MAPERR), or where it came from for
+    /// signals that were sent rather than raised by a fault (zero or less, or SI_KERNEL).
+    pub code: i32,
+    /// For faults, the address of the memory access or instruction at fault.
+    pub addr: usize,
+}
+
+/// A region of the inferior's address space, as listed in /proc/<pid>/maps.
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    pub start: usize,
+    pub end: usize,
+    /// Access permissions, such as "r-xp".
+    pub perms: String,
+    /// The file the region maps, a pseudo-path such as "[heap]" or "[stack]", or empty for an
+    /// anonymous mapping.
+    pub path: String,
+}
+
+/// Computes how far the executable of a stopped process was relocated when it was loaded: the
+/// difference between the runtime entry point (AT_ENTRY in the auxiliary vector) and the entry
+/// point recorded in the ELF header. This is zero for executables that aren't
+/// position-independent.
+fn compute_load_bias(pid: Pid) -> Result<usize, std::io::Error> {
+    let auxv = fs::read(format!("/proc/{}/auxv", pid))?;
+    let runtime_entry = auxv
+        .chunks_exact(2 * size_of::<u64>())
+        .find_map(|entry| {
+            let key = u64::from_le_bytes(entry[..8].try_into().unwrap());
+            let value = u64::from_le_bytes(entry[8..].try_into().unwrap());
+            if key == libc::AT_ENTRY {
+                Some(value)
+            } else {
+                None
+            }
+        })
+        .ok_or_else(|| std::io::Error::other("no AT_ENTRY in auxv"))?;
+
+    // e_entry lives at offset 24 of the ELF64 header
+    let mut header = [0u8; 32];
+    fs::File::open(format!("/proc/{}/exe", pid))?.read_exact(&mut header)?;
+    let elf_entry = u64::from_le_bytes(header[24..32].try_into().unwrap());
+    Ok(runtime_entry.wrapping_sub(elf_entry) as usize)
+}
+
+/// Reads debug register DR<index> of a thread.
+fn peek_debug_register(tid: Pid, index: usize) -> Result<u64, nix::Error> {
+    let ret = unsafe {
+        nix::errno::Errno::clear();
+        libc::ptrace(
+            libc::PTRACE_PEEKUSER,
+            tid.as_raw(),
+            DEBUG_REGISTERS_OFFSET + index * size_of::<u64>(),
+            std::ptr::null_mut::<libc::c_void>(),
+        )
+    };
+    // PEEKUSER returns the value itself, so -1 is only an error if errno was set
+    match nix::errno::Errno::result(ret) {
+        Ok(_) | Err(nix::Error::Sys(nix::errno::Errno::UnknownErrno)) => Ok(ret as u64),
+        Err(err) => Err(err),
+    }
+}
+
+/// Writes debug register DR<index> of a thread.
+fn poke_debug_register(tid: Pid, index: usize, value: u64) -> Result<(), nix::Error> {
+    let ret = unsafe {
+        libc::ptrace(
+            libc::PTRACE_POKEUSER,
+            tid.as_raw(),
+            DEBUG_REGISTERS_OFFSET + index * size_of::<u64>(),
+            value,
+        )
+    };
+    nix::errno::Errno::result(ret).map(drop)
+}
+
+/// The process that Ctrl+C is forwarded to while the debugger waits for it, or 0 if there is none.
+static INTERRUPT_TARGET: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn forward_interrupt(_: std::os::raw::c_int) {
+    let pid = INTERRUPT_TARGET.load(Ordering::SeqCst);
+    if pid != 0 {
+        unsafe { libc::kill(pid, libc::SIGINT) };
+    }
+}
+
+/// Installs the SIGINT handler of the debugger, so that Ctrl+C interrupts the inferior rather than
+/// the debugger. A launched inferior shares our process group and gets the signal from the
+/// terminal directly; other processes (such as attached ones) are sent it by the handler.
+/// SA_RESTART keeps the interrupted waitpid going until the inferior reports the stop.
+pub fn install_interrupt_handler() -> Result<(), nix::Error> {
+    let action = signal::SigAction::new(
+        signal::SigHandler::Handler(forward_interrupt),
+        signal::SaFlags::SA_RESTART,
+        signal::SigSet::empty(),
+    );
+    unsafe { signal::sigaction(signal::Signal::SIGINT, &action) }.map(drop)
 }
 
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
 /// pre_exec with Command to call this in the child process.
 fn child_traceme() -> Result<(), std::io::Error> {
-    ptrace::traceme().or(Err(std::io::Error::new(
-        std::io::ErrorKind::Other,
-        "ptrace TRACEME failed",
-    )))
+    ptrace::traceme().or(Err(std::io::Error::other("ptrace TRACEME failed")))
 }
 
 pub struct Inferior {
     breakpoints: HashMap<usize, u8>,
-    child: Child,
+    pid: Pid,
+    /// The threads of the process by thread number, counting from 1 in order of creation
+    threads: BTreeMap<usize, Pid>,
+    next_thread: usize,
+    /// The thread whose registers we inspect and which steps: the one that stopped last, unless
+    /// another one was selected since
+    thread: Pid,
+    /// Whether only the current thread is running (being single-stepped) rather than all of them
+    stepping: bool,
+    /// New threads and processes whose initial stop arrived before their parent reported creating
+    /// them
+    early_threads: HashSet<Pid>,
+    /// Signals that stopped threads (other than our own SIGTRAPs and SIGSTOPs), to be delivered
+    /// when they are resumed unless they are in `nopass_signals`
+    pending_signals: HashMap<Pid, signal::Signal>,
+    /// Signals the program doesn't get to see (see `handle`)
+    nopass_signals: HashSet<signal::Signal>,
+    /// Threads that hit a breakpoint while we were stopping them ourselves. They were moved back
+    /// onto it without the hit being reported, so that they hit it again once resumed.
+    rewound: HashSet<Pid>,
+    /// Which process to keep debugging after a fork, and whether to detach from the other one
+    follow_fork_mode: FollowForkMode,
+    detach_on_fork: bool,
+    /// Threads of processes we stopped following after a fork but keep stopped rather than
+    /// detaching from (see `detach_on_fork`)
+    suspended: Vec<Pid>,
+    /// The threads of the parent of a vfork we followed into the child. The two share memory
+    /// until the child execs or exits, so only then can our breakpoints be taken out of it.
+    vfork_parent: Vec<Pid>,
+    /// How far the executable was relocated from its link-time addresses (non-zero for
+    /// position-independent executables). DwarfData deals in link-time addresses, so this must be
+    /// added to them before touching the process and subtracted from addresses read out of it.
+    load_bias: usize,
+    /// Whether we attached to an already-running process rather than spawning it ourselves
+    attached: bool,
+    /// The call frame information of the shared libraries unwound through so far, by path, or
+    /// None for those it couldn't be loaded for. Filled in while unwinding, which only borrows
+    /// the inferior.
+    frame_tables: RefCell<HashMap<String, Option<FrameTable>>>,
 }
 
 impl Inferior {
     fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
         let aligned_addr = align_addr_to_word(addr);
         let byte_offset = addr - aligned_addr;
-        let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
+        let word = ptrace::read(self.thread, aligned_addr as ptrace::AddressType)? as u64;
         let orig_byte = (word >> (8 * byte_offset)) & 0xff;
         let masked_word = word & !(0xff << (8 * byte_offset));
         let updated_word = masked_word | ((val as u64) << (8 * byte_offset));
         ptrace::write(
-            self.pid(),
+            self.thread,
             aligned_addr as ptrace::AddressType,
             updated_word as *mut std::ffi::c_void,
         )?;
         Ok(orig_byte as u8)
     }
 
+    /// Writes bytes into the inferior's memory. Bytes under an installed breakpoint replace the
+    /// original byte saved for it, so that the breakpoint stays in place.
+    pub fn write_bytes(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        let end = addr
+            .checked_add(bytes.len())
+            .ok_or(nix::Error::Sys(nix::errno::Errno::EFAULT))?;
+        let mut word_addr = align_addr_to_word(addr);
+        while word_addr < end {
+            let word = ptrace::read(self.thread, word_addr as ptrace::AddressType)? as u64;
+            let mut word = word.to_le_bytes();
+            for (i, byte) in word.iter_mut().enumerate() {
+                let byte_addr = word_addr + i;
+                if byte_addr >= addr && byte_addr < end {
+                    let value = bytes[byte_addr - addr];
+                    match self.breakpoints.get_mut(&byte_addr) {
+                        Some(orig_byte) => *orig_byte = value,
+                        None => *byte = value,
+                    }
+                }
+            }
+            ptrace::write(
+                self.thread,
+                word_addr as ptrace::AddressType,
+                u64::from_le_bytes(word) as *mut std::ffi::c_void,
+            )?;
+            word_addr += size_of::<usize>();
+        }
+        Ok(())
+    }
+
+    /// Reads `len` bytes of the inferior's memory starting at `addr`. Any breakpoints we have
+    /// installed in that range are reported with their original byte rather than 0xcc.
+    pub fn read_bytes(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let end = addr
+            .checked_add(len)
+            .ok_or(nix::Error::Sys(nix::errno::Errno::EFAULT))?;
+        let mut bytes = Vec::new();
+        let mut word_addr = align_addr_to_word(addr);
+        while word_addr < end {
+            let word = ptrace::read(self.thread, word_addr as ptrace::AddressType)? as u64;
+            for (i, byte) in word.to_le_bytes().iter().enumerate() {
+                let byte_addr = word_addr + i;
+                if byte_addr >= addr && byte_addr < end {
+                    bytes.push(*self.breakpoints.get(&byte_addr).unwrap_or(byte));
+                }
+            }
+            word_addr += size_of::<usize>();
+        }
+        Ok(bytes)
+    }
+
+    /// Reads the current value of a variable, as seen from the given frame, out of the stopped
+    /// inferior.
+    pub fn read_variable(
+        &self,
+        var: &Variable,
+        frame: &Frame,
+        debug_data: &DwarfData,
+    ) -> Result<Vec<u8>, ReadError> {
+        let size = var.entity_type.size;
+        let pieces = self.evaluate_location(&var.location, frame, debug_data)?;
+        let mut bytes = Vec::with_capacity(size);
+        for (piece, piece_size) in pieces {
+            let len = piece_size.unwrap_or_else(|| size.saturating_sub(bytes.len()));
+            let mut piece_bytes = match piece {
+                Piece::Memory(addr) => self.read_bytes(addr, len)?,
+                Piece::Register(register) => self.read_register(frame, register)?,
+                Piece::Value(value) => value.to_le_bytes().to_vec(),
+                Piece::Bytes(data) => data,
+                Piece::OptimizedOut => return Err(ReadError::OptimizedOut),
+            };
+            piece_bytes.resize(len, 0);
+            bytes.extend(piece_bytes);
+        }
+        bytes.resize(size, 0);
+        Ok(bytes)
+    }
+
+    /// Stores a new value (already converted to the variable's type) into a variable. Variables
+    /// kept in registers can only be changed in the innermost frame.
+    pub fn write_variable(
+        &mut self,
+        var: &Variable,
+        frame: &Frame,
+        debug_data: &DwarfData,
+        bytes: &[u8],
+    ) -> Result<(), ReadError> {
+        let pieces = self.evaluate_location(&var.location, frame, debug_data)?;
+        let mut offset = 0;
+        for (piece, piece_size) in pieces {
+            let len = piece_size
+                .unwrap_or(bytes.len())
+                .min(bytes.len().saturating_sub(offset));
+            let data = &bytes[offset..offset + len];
+            match piece {
+                Piece::Memory(addr) => self.write_bytes(addr, data)?,
+                Piece::Register(register)
+                    if frame.level == 0 && (register as usize) < DWARF_RA && len <= 8 =>
+                {
+                    let mut value = self.frame_register(frame, register)?.to_le_bytes();
+                    value[..len].copy_from_slice(data);
+                    self.set_register(
+                        DWARF_REGISTER_NAMES[register as usize],
+                        u64::from_le_bytes(value),
+                    )?;
+                }
+                Piece::OptimizedOut => return Err(ReadError::OptimizedOut),
+                _ => return Err(ReadError::NotInMemory),
+            }
+            offset += len;
+        }
+        Ok(())
+    }
+
+    /// Returns the value of a register (named without the `$`) in the given frame. Registers
+    /// other than the general-purpose ones are only known in the innermost frame.
+    pub fn register_value(&self, frame: &Frame, name: &str) -> Result<u64, ReadError> {
+        let name = canonical_register(name);
+        if let Some(number) = DWARF_REGISTER_NAMES.iter().position(|n| *n == name) {
+            return frame.registers[number].ok_or(ReadError::OptimizedOut);
+        }
+        let mut regs = ptrace::getregs(self.thread)?;
+        match register_field(&mut regs, name) {
+            Some(_) if frame.level != 0 => Err(ReadError::OptimizedOut),
+            Some(value) => Ok(*value),
+            None => Err(ReadError::UnknownRegister(name.to_string())),
+        }
+    }
+
+    /// Changes the value of a register (named without the `$`) of the stopped inferior.
+    pub fn set_register(&self, name: &str, value: u64) -> Result<(), ReadError> {
+        let mut regs = ptrace::getregs(self.thread)?;
+        let field = register_field(&mut regs, name)
+            .ok_or_else(|| ReadError::UnknownRegister(name.to_string()))?;
+        *field = value;
+        ptrace::setregs(self.thread, regs)?;
+        Ok(())
+    }
+
+    /// Computes the address at which a variable lives in the given frame. (Globals live in the
+    /// same place no matter the frame.)
+    pub fn variable_address(
+        &self,
+        var: &Variable,
+        frame: &Frame,
+        debug_data: &DwarfData,
+    ) -> Result<usize, ReadError> {
+        match self
+            .evaluate_location(&var.location, frame, debug_data)?
+            .as_slice()
+        {
+            [(Piece::Memory(addr), None)] => Ok(*addr),
+            [(Piece::OptimizedOut, _)] => Err(ReadError::OptimizedOut),
+            _ => Err(ReadError::NotInMemory),
+        }
+    }
+
+    /// Works out where a variable with the given location lives while `frame` executes, as a list
+    /// of pieces and their sizes. A single piece without a size holds the whole variable.
+    fn evaluate_location(
+        &self,
+        location: &Location,
+        frame: &Frame,
+        debug_data: &DwarfData,
+    ) -> Result<Vec<(Piece, Option<usize>)>, ReadError> {
+        let ops = match location {
+            Location::Address(addr) => {
+                return Ok(vec![(
+                    Piece::Memory(addr.wrapping_add(self.load_bias)),
+                    None,
+                )])
+            }
+            Location::FramePointerOffset(offset) => {
+                let frame_base = self.frame_base(frame, debug_data)?;
+                let addr = frame_base.wrapping_add(*offset as u64) as usize;
+                return Ok(vec![(Piece::Memory(addr), None)]);
+            }
+            Location::Expression(ops) => ops,
+            Location::List(entries) => {
+                let pc = frame.lookup_pc().wrapping_sub(self.load_bias);
+                match entries
+                    .iter()
+                    .find(|entry| entry.begin <= pc && pc < entry.end)
+                {
+                    Some(entry) => &entry.expression,
+                    None => return Err(ReadError::OptimizedOut),
+                }
+            }
+            Location::OptimizedOut => return Err(ReadError::OptimizedOut),
+        };
+        self.evaluate_expression(ops, frame, debug_data)
+    }
+
+    /// Evaluates the frame base (DW_AT_frame_base) of the function executing in `frame`, which
+    /// DW_OP_fbreg offsets are relative to.
+    fn frame_base(&self, frame: &Frame, debug_data: &DwarfData) -> Result<u64, ReadError> {
+        let pc = frame.lookup_pc().wrapping_sub(self.load_bias);
+        let function = debug_data
+            .get_function_containing(pc)
+            .ok_or(ReadError::InvalidExpression)?;
+        match function.frame_base.as_slice() {
+            [] | [Op::CallFrameCfa] => Ok(frame.cfa as u64),
+            // A register as the frame base means the address held in that register
+            [Op::Register(register)] => self.frame_register(frame, *register),
+            ops => match self.evaluate_expression(ops, frame, debug_data)?.as_slice() {
+                [(Piece::Memory(addr), None)] => Ok(*addr as u64),
+                _ => Err(ReadError::InvalidExpression),
+            },
+        }
+    }
+
+    /// Runs a DWARF expression on a stack machine, with registers taken from `frame` and memory
+    /// read from the inferior.
+    fn evaluate_expression(
+        &self,
+        ops: &[Op],
+        frame: &Frame,
+        debug_data: &DwarfData,
+    ) -> Result<Vec<(Piece, Option<usize>)>, ReadError> {
+        fn pop(stack: &mut Vec<u64>) -> Result<u64, ReadError> {
+            stack.pop().ok_or(ReadError::InvalidExpression)
+        }
+
+        let mut stack: Vec<u64> = Vec::new();
+        let mut pieces = Vec::new();
+        // The location described by the last operation, when it isn't an address on the stack
+        let mut current: Option<Piece> = None;
+        let mut index = 0;
+        let mut steps = 0;
+        while let Some(op) = ops.get(index) {
+            index += 1;
+            // Branches can go backwards, so a malformed expression could loop forever
+            steps += 1;
+            if steps > MAX_EXPRESSION_STEPS {
+                return Err(ReadError::InvalidExpression);
+            }
+            let value = match op {
+                Op::Address(addr) => addr.wrapping_add(self.load_bias as u64),
+                Op::Constant(value) => *value,
+                Op::Register(register) => {
+                    current = Some(Piece::Register(*register));
+                    continue;
+                }
+                Op::RegisterOffset(register, offset) => self
+                    .frame_register(frame, *register)?
+                    .wrapping_add(*offset as u64),
+                Op::FrameOffset(offset) => self
+                    .frame_base(frame, debug_data)?
+                    .wrapping_add(*offset as u64),
+                Op::CallFrameCfa => frame.cfa as u64,
+                Op::Deref(size) => {
+                    let addr = pop(&mut stack)? as usize;
+                    let mut bytes = self.read_bytes(addr, *size as usize)?;
+                    bytes.resize(8, 0);
+                    u64::from_le_bytes(bytes[..8].try_into().unwrap())
+                }
+                Op::Pick(depth) => *stack
+                    .iter()
+                    .rev()
+                    .nth(*depth as usize)
+                    .ok_or(ReadError::InvalidExpression)?,
+                Op::Drop => {
+                    pop(&mut stack)?;
+                    continue;
+                }
+                Op::Swap => {
+                    let top = pop(&mut stack)?;
+                    let second = pop(&mut stack)?;
+                    stack.push(top);
+                    second
+                }
+                Op::Rot => {
+                    let top = pop(&mut stack)?;
+                    let second = pop(&mut stack)?;
+                    let third = pop(&mut stack)?;
+                    stack.push(top);
+                    stack.push(third);
+                    second
+                }
+                Op::Abs => (pop(&mut stack)? as i64).wrapping_abs() as u64,
+                Op::Neg => (pop(&mut stack)? as i64).wrapping_neg() as u64,
+                Op::Not => !pop(&mut stack)?,
+                Op::PlusConstant(constant) => pop(&mut stack)?.wrapping_add(*constant),
+                Op::Bra(target) => {
+                    if pop(&mut stack)? != 0 {
+                        index = *target;
+                    }
+                    continue;
+                }
+                Op::Skip(target) => {
+                    index = *target;
+                    continue;
+                }
+                Op::Nop => continue,
+                Op::Piece(size) => {
+                    let piece = match current.take() {
+                        Some(piece) => piece,
+                        None => match stack.pop() {
+                            Some(addr) => Piece::Memory(addr as usize),
+                            None => Piece::OptimizedOut,
+                        },
+                    };
+                    pieces.push((piece, Some(*size)));
+                    continue;
+                }
+                Op::StackValue => {
+                    current = Some(Piece::Value(pop(&mut stack)?));
+                    continue;
+                }
+                Op::ImplicitValue(bytes) => {
+                    current = Some(Piece::Bytes(bytes.clone()));
+                    continue;
+                }
+                // The value a register had on entry to the function is only recoverable by
+                // searching the callers, which we don't attempt
+                Op::Unsupported(name) if name.ends_with("entry_value") => {
+                    return Err(ReadError::OptimizedOut)
+                }
+                Op::Unsupported(name) => return Err(ReadError::Unsupported(name.clone())),
+                binary => {
+                    let rhs = pop(&mut stack)?;
+                    let lhs = pop(&mut stack)?;
+                    match binary {
+                        Op::And => lhs & rhs,
+                        Op::Or => lhs | rhs,
+                        Op::Xor => lhs ^ rhs,
+                        Op::Plus => lhs.wrapping_add(rhs),
+                        Op::Minus => lhs.wrapping_sub(rhs),
+                        Op::Mul => lhs.wrapping_mul(rhs),
+                        Op::Div if rhs == 0 => return Err(ReadError::InvalidExpression),
+                        Op::Div => (lhs as i64).wrapping_div(rhs as i64) as u64,
+                        Op::Mod if rhs == 0 => return Err(ReadError::InvalidExpression),
+                        Op::Mod => lhs % rhs,
+                        Op::Shl => lhs.checked_shl(rhs as u32).unwrap_or(0),
+                        Op::Shr => lhs.checked_shr(rhs as u32).unwrap_or(0),
+                        Op::Shra => (lhs as i64 >> rhs.min(63)) as u64,
+                        Op::Eq => (lhs == rhs) as u64,
+                        Op::Ne => (lhs != rhs) as u64,
+                        Op::Ge => (lhs as i64 >= rhs as i64) as u64,
+                        Op::Gt => (lhs as i64 > rhs as i64) as u64,
+                        Op::Le => (lhs as i64 <= rhs as i64) as u64,
+                        Op::Lt => ((lhs as i64) < rhs as i64) as u64,
+                        _ => unreachable!(),
+                    }
+                }
+            };
+            stack.push(value);
+        }
+
+        if pieces.is_empty() {
+            let piece = match current {
+                Some(piece) => piece,
+                None => match stack.pop() {
+                    Some(addr) => Piece::Memory(addr as usize),
+                    // An empty expression means the variable doesn't exist here
+                    None => Piece::OptimizedOut,
+                },
+            };
+            pieces.push((piece, None));
+        }
+        Ok(pieces)
+    }
+
+    /// Returns the value of a general-purpose register in the given frame.
+    fn frame_register(&self, frame: &Frame, register: u16) -> Result<u64, ReadError> {
+        match frame.registers.get(register as usize) {
+            // Registers that weren't saved by the callees are lost
+            Some(value) => value.ok_or(ReadError::OptimizedOut),
+            None => Err(ReadError::Unsupported(format!("register {}", register))),
+        }
+    }
+
+    /// Returns the contents of a register in the given frame. The vector registers are only known
+    /// for the innermost frame, since they are never saved across calls.
+    fn read_register(&self, frame: &Frame, register: u16) -> Result<Vec<u8>, ReadError> {
+        if (DWARF_XMM0..=DWARF_XMM15).contains(&register) && frame.level == 0 {
+            let xmm = self.read_xmm((register - DWARF_XMM0) as usize)?;
+            return Ok(xmm.to_le_bytes().to_vec());
+        }
+        Ok(self.frame_register(frame, register)?.to_le_bytes().to_vec())
+    }
+
+    /// Reads the low 64 bits of xmm register `index`.
+    fn read_xmm(&self, index: usize) -> Result<u64, nix::Error> {
+        let mut fpregs: libc::user_fpregs_struct = unsafe { std::mem::zeroed() };
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETFPREGS,
+                self.thread.as_raw(),
+                std::ptr::null_mut::<libc::c_void>(),
+                &mut fpregs as *mut libc::user_fpregs_struct,
+            )
+        };
+        nix::errno::Errno::result(res)?;
+        // Each xmm register takes up four 32-bit words
+        let words = &fpregs.xmm_space[4 * index..4 * index + 2];
+        Ok(words[0] as u64 | (words[1] as u64) << 32)
+    }
+
+    /// Arms hardware watchpoint `slot` (one of DR0-DR3) so that the inferior stops after any write
+    /// to the `len` bytes at `addr`. `len` must be 1, 2, 4 or 8 and `addr` aligned to it.
+    pub fn set_watchpoint(
+        &mut self,
+        slot: usize,
+        addr: usize,
+        len: usize,
+    ) -> Result<(), nix::Error> {
+        let len_bits = match len {
+            1 => 0b00,
+            2 => 0b01,
+            4 => 0b11,
+            8 => 0b10,
+            _ => return Err(nix::Error::invalid_argument()),
+        };
+        if slot >= 4 || !addr.is_multiple_of(len) {
+            return Err(nix::Error::invalid_argument());
+        }
+        let mut dr7 = peek_debug_register(self.thread, 7)?;
+        // Clear the slot's condition/length bits, then set R/W = 01 (break on data writes) and
+        // the local enable bit
+        dr7 &= !(0b1111 << (16 + 4 * slot));
+        dr7 |= (0b01 | len_bits << 2) << (16 + 4 * slot);
+        dr7 |= 1 << (2 * slot);
+        // Debug registers are per thread, so arm the watchpoint in all of them
+        for &tid in self.threads.values() {
+            poke_debug_register(tid, slot, addr as u64)?;
+            poke_debug_register(tid, 7, dr7)?;
+        }
+        Ok(())
+    }
+
+    /// Disarms hardware watchpoint `slot`.
+    pub fn clear_watchpoint(&mut self, slot: usize) -> Result<(), nix::Error> {
+        let dr7 = peek_debug_register(self.thread, 7)? & !(1 << (2 * slot));
+        for &tid in self.threads.values() {
+            poke_debug_register(tid, 7, dr7)?;
+            poke_debug_register(tid, slot, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Returns which hardware watchpoint caused the last stop, if any, and resets the debug status
+    /// register (DR6) so the next stop starts clean.
+    pub fn take_triggered_watchpoint(&self) -> Result<Option<usize>, nix::Error> {
+        let dr6 = peek_debug_register(self.thread, 6)?;
+        if dr6 & 0b1111 == 0 {
+            return Ok(None);
+        }
+        poke_debug_register(self.thread, 6, 0)?;
+        Ok((0..4).find(|slot| dr6 & (1 << slot) != 0))
+    }
+
+    /// Returns the current instruction pointer of the inferior.
+    pub fn get_pc(&self) -> Result<usize, nix::Error> {
+        Ok(ptrace::getregs(self.thread)?.rip as usize)
+    }
+
+    /// Returns the current stack pointer of the inferior.
+    pub fn get_sp(&self) -> Result<usize, nix::Error> {
+        Ok(ptrace::getregs(self.thread)?.rsp as usize)
+    }
+
     pub fn set_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        if self.breakpoints.contains_key(&addr) {
+            return Ok(());
+        }
         let orig_byte = self.write_byte(addr, 0xcc)?;
         self.breakpoints.insert(addr, orig_byte);
         Ok(())
     }
 
+    /// Removes a breakpoint, restoring the original instruction byte.
+    pub fn remove_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        if let Some(orig_byte) = self.breakpoints.remove(&addr) {
+            self.write_byte(addr, orig_byte)?;
+        }
+        Ok(())
+    }
+
     /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
     /// an error is encountered.
     pub fn new(target: &str, args: &Vec<String>) -> Option<Inferior> {
@@ -70,84 +871,978 @@ impl Inferior {
         unsafe {
             cmd.pre_exec(child_traceme);
         }
-        let inferior = Inferior {
+        let child = cmd.spawn().ok()?;
+        let mut inferior = Inferior::with_pid(Pid::from_raw(child.id() as i32), false);
+        loop {
+            match inferior.wait(None).ok()? {
+                Status::Stopped(signal::Signal::SIGTRAP, _) => break,
+                // A Ctrl+C that arrived before the exec: discard it and keep going
+                Status::Stopped(_, _) => {
+                    inferior.pending_signals.clear();
+                    ptrace::cont(inferior.pid(), None).ok()?;
+                }
+                _ => return None,
+            }
+        }
+        ptrace::setoptions(inferior.pid(), trace_options()).ok()?;
+        inferior.load_bias = compute_load_bias(inferior.pid()).ok()?;
+        Some(inferior)
+    }
+
+    /// Attaches to an already-running process with PTRACE_ATTACH, waiting until it stops. Every
+    /// thread of the process is attached to.
+    pub fn attach(pid: Pid) -> Result<Inferior, nix::Error> {
+        ptrace::attach(pid)?;
+        let mut inferior = Inferior::with_pid(pid, true);
+        match inferior.attach_threads() {
+            Ok(()) => Ok(inferior),
+            Err(err) => {
+                // Don't leave the process stopped, traced by a debugger that has given up on it
+                let _ = inferior.detach();
+                Err(err)
+            }
+        }
+    }
+
+    /// Waits for the process just attached to to stop, then attaches to its other threads.
+    fn attach_threads(&mut self) -> Result<(), nix::Error> {
+        let pid = self.pid;
+        match self.wait(None)? {
+            Status::Stopped(_, _) => {
+                ptrace::setoptions(pid, trace_options())?;
+                self.load_bias =
+                    compute_load_bias(pid).map_err(|_| nix::Error::Sys(nix::errno::Errno::EIO))?;
+            }
+            _ => return Err(nix::Error::Sys(nix::errno::Errno::ESRCH)),
+        }
+        let tasks = fs::read_dir(format!("/proc/{}/task", pid))
+            .map_err(|_| nix::Error::Sys(nix::errno::Errno::EIO))?;
+        let mut tids: Vec<Pid> = tasks
+            .filter_map(|task| task.ok()?.file_name().to_str()?.parse().ok())
+            .map(Pid::from_raw)
+            .filter(|&tid| tid != pid)
+            .collect();
+        tids.sort_by_key(|tid| tid.as_raw());
+        for tid in tids {
+            // The thread may have exited in the meantime
+            if ptrace::attach(tid).is_err() {
+                continue;
+            }
+            // Recorded right away, so that it is detached from again if anything goes wrong
+            self.threads.insert(self.next_thread, tid);
+            self.next_thread += 1;
+            waitpid(tid, Some(WaitPidFlag::__WALL))?;
+            ptrace::setoptions(tid, trace_options())?;
+        }
+        Ok(())
+    }
+
+    fn with_pid(pid: Pid, attached: bool) -> Inferior {
+        Inferior {
             breakpoints: HashMap::new(),
-            child: cmd.spawn().ok()?,
-        };
-        // SIGINT here will cause a deadlock
-        match inferior.wait(None).ok()? {
-            Status::Stopped(signal::Signal::SIGTRAP, _) => Some(inferior),
-            _ => None,
+            pid,
+            threads: vec![(1, pid)].into_iter().collect(),
+            next_thread: 2,
+            thread: pid,
+            stepping: false,
+            early_threads: HashSet::new(),
+            pending_signals: HashMap::new(),
+            nopass_signals: HashSet::new(),
+            rewound: HashSet::new(),
+            follow_fork_mode: FollowForkMode::Parent,
+            detach_on_fork: true,
+            suspended: Vec::new(),
+            vfork_parent: Vec::new(),
+            load_bias: 0,
+            attached,
+            frame_tables: RefCell::new(HashMap::new()),
         }
     }
 
-    pub fn print_backtrace(&self, debug_data: &DwarfData) -> Result<(), nix::Error> {
-        let regs = ptrace::getregs(self.pid())?;
-        let mut rip = regs.rip as usize;
-        let mut rbp = regs.rbp as usize;
+    /// Returns the offset between the executable's link-time addresses (as used by DwarfData) and
+    /// where it actually got loaded.
+    pub fn load_bias(&self) -> usize {
+        self.load_bias
+    }
 
-        loop {
-            let line = debug_data.get_line_from_addr(rip).unwrap();
-            let function = debug_data.get_function_from_addr(rip).unwrap();
-            println!("{} ({}:{})", function, line.file, line.number);
+    /// Returns whether this inferior was attached to (rather than spawned by us).
+    pub fn is_attached(&self) -> bool {
+        self.attached
+    }
+
+    /// Returns the details of the signal the current thread is stopped by.
+    pub fn signal_info(&self) -> Result<SignalInfo, nix::Error> {
+        let info = ptrace::getsiginfo(self.thread)?;
+        Ok(SignalInfo {
+            code: info.si_code,
+            addr: unsafe { info.si_addr() } as usize,
+        })
+    }
+
+    /// Returns the memory mappings of the inferior, sorted by address.
+    pub fn memory_maps(&self) -> Result<Vec<Mapping>, std::io::Error> {
+        let maps = fs::read_to_string(format!("/proc/{}/maps", self.pid))?;
+        let parse_error = || std::io::Error::new(std::io::ErrorKind::InvalidData, "malformed maps");
+        maps.lines()
+            .map(|line| {
+                // start-end perms offset dev inode [path]
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let mut range = fields.first().ok_or_else(parse_error)?.split('-');
+                let mut bound = || {
+                    range
+                        .next()
+                        .and_then(|bound| usize::from_str_radix(bound, 16).ok())
+                        .ok_or_else(parse_error)
+                };
+                Ok(Mapping {
+                    start: bound()?,
+                    end: bound()?,
+                    perms: fields.get(1).ok_or_else(parse_error)?.to_string(),
+                    path: fields
+                        .get(5..)
+                        .map(|path| path.join(" "))
+                        .unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
 
-            if function == "main" {
+    /// Removes all of our breakpoints and watchpoints from the process and lets it run freely.
+    /// Failures don't stop the rest of the process from being let go of; the first one is
+    /// returned.
+    pub fn detach(&mut self) -> Result<(), nix::Error> {
+        let mut result = Ok(());
+        let addrs: Vec<usize> = self.breakpoints.keys().cloned().collect();
+        for addr in addrs {
+            result = result.and(self.remove_breakpoint(addr));
+        }
+        let tids: Vec<Pid> = self.threads.values().cloned().collect();
+        for tid in tids {
+            let cleared = poke_debug_register(tid, 7, 0);
+            let signal = self.take_signal(tid);
+            let detached = ptrace::detach(tid, signal);
+            result = result.and(cleared).and(detached);
+        }
+        result.and(self.release_forks())
+    }
+
+    /// Sets whether a signal that stops a thread is delivered to it when it is resumed.
+    pub fn set_signal_pass(&mut self, signal: signal::Signal, pass: bool) {
+        if pass {
+            self.nopass_signals.remove(&signal);
+        } else {
+            self.nopass_signals.insert(signal);
+        }
+    }
+
+    /// Returns the signal to deliver to a thread when resuming it, if any.
+    fn take_signal(&mut self, tid: Pid) -> Option<signal::Signal> {
+        let signal = self.pending_signals.remove(&tid)?;
+        if self.nopass_signals.contains(&signal) {
+            None
+        } else {
+            Some(signal)
+        }
+    }
+
+    /// Sets which process to keep debugging when the inferior forks, and whether to detach from
+    /// the other one or keep it stopped.
+    pub fn set_fork_policy(&mut self, follow_fork_mode: FollowForkMode, detach_on_fork: bool) {
+        self.follow_fork_mode = follow_fork_mode;
+        self.detach_on_fork = detach_on_fork;
+    }
+
+    /// Returns the threads of the process by thread number.
+    pub fn threads(&self) -> &BTreeMap<usize, Pid> {
+        &self.threads
+    }
+
+    /// Returns the number of the current thread.
+    pub fn current_thread(&self) -> usize {
+        self.thread_number(self.thread)
+    }
+
+    fn thread_number(&self, tid: Pid) -> usize {
+        self.threads
+            .iter()
+            .find(|(_, &thread)| thread == tid)
+            .map_or(0, |(&num, _)| num)
+    }
+
+    /// Makes the thread with the given number the current one, whose registers (and thereby
+    /// stack) are inspected and which steps. Returns false if there is no such thread.
+    pub fn select_thread(&mut self, num: usize) -> bool {
+        match self.threads.get(&num) {
+            Some(&tid) => {
+                self.thread = tid;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Recovers the call stack using the call frame information of the executable and the shared
+    /// libraries it uses, innermost frame first. Where there is no CFI for a function we have
+    /// debug info for, we fall back to assuming it keeps a frame pointer. The walk ends at the
+    /// outermost frame (whose return address is undefined), or at the first frame we don't know
+    /// how to unwind.
+    pub fn unwind(&self, debug_data: &DwarfData) -> Result<Vec<Frame>, nix::Error> {
+        self.unwind_frames(debug_data, MAX_FRAMES)
+    }
+
+    /// Returns the innermost frame, without unwinding the rest of the stack.
+    pub fn current_frame(&self, debug_data: &DwarfData) -> Result<Frame, nix::Error> {
+        self.unwind_frames(debug_data, 1)?
+            .pop()
+            .ok_or(nix::Error::Sys(nix::errno::Errno::EFAULT))
+    }
+
+    fn unwind_frames(
+        &self,
+        debug_data: &DwarfData,
+        limit: usize,
+    ) -> Result<Vec<Frame>, nix::Error> {
+        let regs = ptrace::getregs(self.thread)?;
+        let mut registers = [None; DWARF_REGISTER_COUNT];
+        let values = [
+            regs.rax, regs.rdx, regs.rcx, regs.rbx, regs.rsi, regs.rdi, regs.rbp, regs.rsp,
+            regs.r8, regs.r9, regs.r10, regs.r11, regs.r12, regs.r13, regs.r14, regs.r15, regs.rip,
+        ];
+        for (register, value) in registers.iter_mut().zip(values.iter()) {
+            *register = Some(*value);
+        }
+
+        let mut frames: Vec<Frame> = Vec::new();
+        while let Some(pc) = registers[DWARF_RA] {
+            let pc = pc as usize;
+            if pc == 0 || frames.len() == limit {
+                break;
+            }
+            let runtime_lookup_pc = if frames.is_empty() { pc } else { pc - 1 };
+            let lookup_pc = runtime_lookup_pc.wrapping_sub(self.load_bias);
+            let in_executable = debug_data.get_function_containing(lookup_pc).is_some();
+            let row = debug_data.get_unwind_row(lookup_pc).or_else(|| {
+                if in_executable {
+                    None
+                } else {
+                    self.library_unwind_row(runtime_lookup_pc)
+                }
+            });
+            let (cfa, rules) = match row {
+                Some(row) => {
+                    let base = match registers.get(row.cfa.register as usize) {
+                        Some(Some(value)) => *value,
+                        _ => break,
+                    };
+                    (
+                        base.wrapping_add(row.cfa.offset as u64) as usize,
+                        row.registers,
+                    )
+                }
+                None if in_executable => {
+                    // push rbp; mov rbp, rsp: the saved rbp is at CFA - 16, the return address
+                    // at CFA - 8
+                    let rbp = match registers[DWARF_RBP] {
+                        Some(rbp) => rbp as usize,
+                        None => break,
+                    };
+                    let rules = vec![
+                        (DWARF_RBP as u16, RegisterRule::Offset(-16)),
+                        (DWARF_RA as u16, RegisterRule::Offset(-8)),
+                    ];
+                    (rbp + 16, rules)
+                }
+                None => {
+                    frames.push(Frame {
+                        level: frames.len(),
+                        pc,
+                        cfa: 0,
+                        registers,
+                    });
+                    break;
+                }
+            };
+            // The stack grows down, so each caller's CFA must be above its callee's
+            if frames.last().is_some_and(|frame| cfa <= frame.cfa) {
                 break;
             }
+            frames.push(Frame {
+                level: frames.len(),
+                pc,
+                cfa,
+                registers,
+            });
+            let frame = frames.last().unwrap();
 
-            rip = ptrace::read(self.pid(), (rbp + 8) as ptrace::AddressType).unwrap() as usize;
-            rbp = ptrace::read(self.pid(), rbp as ptrace::AddressType).unwrap() as usize;
+            let mut caller = [None; DWARF_REGISTER_COUNT];
+            for &register in CALLEE_SAVED_REGISTERS.iter() {
+                caller[register] = frame.registers[register];
+            }
+            caller[DWARF_RSP] = Some(cfa as u64);
+            for (register, rule) in rules {
+                let register = register as usize;
+                if register >= DWARF_REGISTER_COUNT {
+                    continue;
+                }
+                caller[register] = match rule {
+                    RegisterRule::Undefined => None,
+                    RegisterRule::SameValue => frame.registers[register],
+                    RegisterRule::Offset(offset) => {
+                        let addr = cfa.wrapping_add(offset as usize);
+                        ptrace::read(self.thread, addr as ptrace::AddressType)
+                            .ok()
+                            .map(|value| value as u64)
+                    }
+                    RegisterRule::ValOffset(offset) => {
+                        Some(cfa.wrapping_add(offset as usize) as u64)
+                    }
+                    RegisterRule::Register(other) => {
+                        frame.registers.get(other as usize).cloned().flatten()
+                    }
+                };
+            }
+            registers = caller;
         }
+        Ok(frames)
+    }
 
-        Ok(())
+    /// Returns the unwind rules at (runtime) address `pc` in whichever shared library is mapped
+    /// there, loading the library's call frame information the first time it is needed.
+    fn library_unwind_row(&self, pc: usize) -> Option<UnwindRow> {
+        let maps = self.memory_maps().ok()?;
+        let path = &maps
+            .iter()
+            .find(|mapping| mapping.start <= pc && pc < mapping.end)?
+            .path;
+        // Pseudo-paths such as [vdso] aren't files we could read
+        if !path.starts_with('/') {
+            return None;
+        }
+        let start = maps
+            .iter()
+            .filter(|mapping| &mapping.path == path)
+            .map(|mapping| mapping.start)
+            .min()?;
+        let mut frame_tables = self.frame_tables.borrow_mut();
+        let frame_table = frame_tables
+            .entry(path.clone())
+            .or_insert_with(|| FrameTable::from_file(path).ok())
+            .as_ref()?;
+        let bias = start.wrapping_sub(frame_table.base_address & !(PAGE_SIZE - 1));
+        frame_table.get_unwind_row(pc.wrapping_sub(bias))
     }
 
-    pub fn continue_exec(&mut self) -> Result<Status, nix::Error> {
-        let mut regs = ptrace::getregs(self.pid())?;
-        let rip = regs.rip as usize;
+    /// Resumes all threads until one of them stops in a way `filter` reports.
+    pub fn continue_exec(&mut self, filter: &mut StopFilter) -> Result<Status, nix::Error> {
+        loop {
+            let status = self.continue_all()?;
+            if filter(self, status) != Some(false) {
+                return Ok(status);
+            }
+        }
+    }
 
-        if let Some(&orig_byte) = self.breakpoints.get(&(rip - 1)) {
-            println!("Stopped at a breakpoint.");
-            self.write_byte(rip - 1, orig_byte)?;
+    /// Resumes all threads until one of them stops. Threads stopped at one of our breakpoints are
+    /// first stepped past it, unless they have yet to report hitting it.
+    fn continue_all(&mut self) -> Result<Status, nix::Error> {
+        let current = self.thread;
+        let tids: Vec<Pid> = self.threads.values().cloned().collect();
+        for tid in tids {
+            if self.rewound.contains(&tid) {
+                continue;
+            }
+            self.thread = tid;
+            if self.breakpoints.contains_key(&self.get_pc()?) {
+                match self.step_instruction()? {
+                    Status::Stopped(signal::Signal::SIGTRAP, _) => {}
+                    other => return Ok(other),
+                }
+            }
+        }
+        self.thread = current;
+        self.rewound.clear();
 
-            regs.rip = (rip - 1) as u64;
-            ptrace::setregs(self.pid(), regs)?;
+        let tids: Vec<Pid> = self.threads.values().cloned().collect();
+        for tid in tids {
+            let signal = self.take_signal(tid);
+            ptrace::cont(tid, signal)?;
+        }
+        self.wait(None)
+    }
 
-            ptrace::step(self.pid(), None)?;
-            match self.wait(None).unwrap() {
-                Status::Stopped(_, _) => {
-                    self.write_byte(rip - 1, 0xcc)?;
+    /// Executes a single machine instruction of the current thread, leaving the other threads
+    /// stopped. If the thread is stopped at one of our breakpoints, the original instruction is
+    /// put back for the duration of the step and the breakpoint is reinserted afterwards.
+    pub fn step_instruction(&mut self) -> Result<Status, nix::Error> {
+        let pc = self.get_pc()?;
+        let orig_byte = self.breakpoints.get(&pc).cloned();
+        if let Some(orig_byte) = orig_byte {
+            self.write_byte(pc, orig_byte)?;
+        }
+        self.stepping = true;
+        let signal = self.take_signal(self.thread);
+        ptrace::step(self.thread, signal)?;
+        let status = self.wait(None);
+        self.stepping = false;
+        let status = status?;
+        if orig_byte.is_some() {
+            if let Status::Stopped(_, _) = status {
+                self.write_byte(pc, 0xcc)?;
+            }
+        }
+        Ok(status)
+    }
+
+    /// Executes a single machine instruction like `step_instruction`, except that a call is run
+    /// to completion rather than stepped into. Stops within the call are passed through `filter`.
+    pub fn step_over_instruction(&mut self, filter: &mut StopFilter) -> Result<Status, nix::Error> {
+        let regs = ptrace::getregs(self.thread)?;
+        let status = self.step_instruction()?;
+        if let Status::Stopped(signal::Signal::SIGTRAP, _) = status {
+            if let Some(return_addr) = self.called_from(regs.rip as usize, regs.rsp as usize)? {
+                return self.run_until_return(return_addr, regs.rsp as usize, filter);
+            }
+        }
+        Ok(status)
+    }
+
+    /// Single-steps until the inferior reaches a different source line. If `step_into` is false,
+    /// or the called function has no debugging information, calls are run to completion instead
+    /// of being stepped into.
+    ///
+    /// Breakpoints, watchpoints and signals met along the way end the step early if `filter`
+    /// reports them; otherwise the step carries on from where it was interrupted.
+    pub fn step_line(
+        &mut self,
+        debug_data: &DwarfData,
+        step_into: bool,
+        filter: &mut StopFilter,
+    ) -> Result<Status, nix::Error> {
+        let start_line = debug_data.get_line_from_addr(self.get_pc()?.wrapping_sub(self.load_bias));
+        loop {
+            let regs = ptrace::getregs(self.thread)?;
+            let status = self.step_instruction()?;
+            let mut pc = match status {
+                Status::Stopped(signal::Signal::SIGTRAP, pc) => pc,
+                // A signal that isn't reported is delivered by the next step
+                Status::Stopped(_, _) if filter(self, status) == Some(false) => continue,
+                other => return Ok(other),
+            };
+            let call_return = match self.called_from(regs.rip as usize, regs.rsp as usize)? {
+                Some(return_addr)
+                    if !step_into
+                        || debug_data
+                            .get_line_from_addr(pc.wrapping_sub(self.load_bias))
+                            .is_none() =>
+                {
+                    Some(return_addr)
+                }
+                _ => None,
+            };
+            if let Some(return_addr) = call_return {
+                // Stops within the call have been through the filter already
+                match self.run_until_return(return_addr, regs.rsp as usize, filter)? {
+                    Status::Stopped(signal::Signal::SIGTRAP, addr) if addr == return_addr => {
+                        pc = addr
+                    }
+                    other => return Ok(other),
                 }
-                Status::Signaled(signal) => return Ok(Status::Signaled(signal)),
-                Status::Exited(status) => return Ok(Status::Exited(status)),
+            } else if filter(self, status) == Some(true) {
+                return Ok(status);
             }
+            match (
+                debug_data.get_line_from_addr(pc.wrapping_sub(self.load_bias)),
+                &start_line,
+            ) {
+                (Some(line), Some(start))
+                    if line.file == start.file && line.number == start.number => {}
+                _ => return Ok(Status::Stopped(signal::Signal::SIGTRAP, pc)),
+            }
+        }
+    }
+
+    /// Checks whether the instruction just stepped over (which was at `prev_pc`, with the stack
+    /// pointer at `prev_sp`) was a call. If so, returns the address the call will return to.
+    fn called_from(&self, prev_pc: usize, prev_sp: usize) -> Result<Option<usize>, nix::Error> {
+        let regs = ptrace::getregs(self.thread)?;
+        if regs.rsp as usize != prev_sp - size_of::<usize>() {
+            return Ok(None);
+        }
+        let pushed = ptrace::read(self.thread, regs.rsp as ptrace::AddressType)? as usize;
+        // A call pushes the address of the next instruction (x86 instructions are at most 15
+        // bytes long) and then jumps somewhere else.
+        if pushed > prev_pc && pushed <= prev_pc + 15 && pushed != regs.rip as usize {
+            Ok(Some(pushed))
+        } else {
+            Ok(None)
         }
+    }
 
-        ptrace::cont(self.pid(), None)?;
-        self.wait(None)
+    /// Returns the address the current function will return to, along with the value the stack
+    /// pointer will have once it does (the current frame's CFA), or None if the caller's frame
+    /// can't be recovered.
+    pub fn return_address(
+        &self,
+        debug_data: &DwarfData,
+    ) -> Result<Option<(usize, usize)>, nix::Error> {
+        let frames = self.unwind(debug_data)?;
+        Ok(frames.get(1).map(|caller| (caller.pc, frames[0].cfa)))
+    }
+
+    /// Reads the value just returned by a function of the given type, following the System V
+    /// calling convention: floating point values come back in xmm0, everything else in rax.
+    /// Typedefs should already have been resolved.
+    pub fn read_return_value(&self, return_type: &Type) -> Result<Vec<u8>, nix::Error> {
+        let bytes = if let TypeKind::Base(Encoding::Float) = return_type.kind {
+            self.read_xmm(0)?.to_le_bytes()
+        } else {
+            ptrace::getregs(self.thread)?.rax.to_le_bytes()
+        };
+        Ok(bytes[..return_type.size.min(bytes.len())].to_vec())
+    }
+
+    /// Continues until the function that was called with the stack pointer at `caller_sp` returns
+    /// to `return_addr`. Returns early if the inferior stops in a way `filter` reports.
+    pub fn run_until_return(
+        &mut self,
+        return_addr: usize,
+        caller_sp: usize,
+        filter: &mut StopFilter,
+    ) -> Result<Status, nix::Error> {
+        let temporary = !self.breakpoints.contains_key(&return_addr);
+        if temporary {
+            self.set_breakpoint(return_addr)?;
+        }
+        let thread = self.thread;
+        let status = loop {
+            let status = self.continue_all()?;
+            match status {
+                // Hitting the return address in a deeper frame means a recursive call returned,
+                // and other threads may run the same code. That is only worth reporting if
+                // there is a breakpoint of the user's there.
+                Status::Stopped(signal::Signal::SIGTRAP, pc)
+                    if pc == return_addr
+                        && (self.thread != thread
+                            || (ptrace::getregs(self.thread)?.rsp as usize) < caller_sp) =>
+                {
+                    if !temporary && filter(self, status) == Some(true) {
+                        break status;
+                    }
+                }
+                Status::Stopped(signal::Signal::SIGTRAP, pc) if pc == return_addr => break status,
+                _ if filter(self, status) == Some(false) => {}
+                _ => break status,
+            }
+        };
+        if temporary {
+            if let Status::Stopped(_, _) = status {
+                self.remove_breakpoint(return_addr)?;
+            }
+        }
+        Ok(status)
     }
 
-    pub fn kill(&mut self) -> Result<(), std::io::Error> {
+    pub fn kill(&mut self) -> Result<(), nix::Error> {
         println!("Killing running inferior (pid {})", self.pid());
-        self.child.kill()
+        signal::kill(self.pid(), signal::Signal::SIGKILL)?;
+        // Reap the process so it doesn't linger as a zombie
+        self.wait(None)?;
+        Ok(())
     }
 
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+        self.pid
     }
 
     /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
     /// after the waitpid call.
-    pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
-            WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
-            WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
-            WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
-                Status::Stopped(signal, regs.rip as usize)
-            }
-            other => panic!("waitpid returned unexpected status: {:?}", other),
-        })
+    ///
+    /// Threads are stopped all together: once one of them stops, the others are stopped as well,
+    /// and the one that stopped becomes the current thread. Threads being created or exiting
+    /// along the way are tracked without reporting a stop.
+    ///
+    /// Ctrl+C is forwarded to the process while waiting if the terminal doesn't deliver it there.
+    pub fn wait(&mut self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
+        let shares_terminal = unistd::getpgid(Some(self.pid)).ok() == Some(unistd::getpgrp());
+        if !shares_terminal {
+            INTERRUPT_TARGET.store(self.pid.as_raw(), Ordering::SeqCst);
+        }
+        let status = self.wait_for_stop(options);
+        INTERRUPT_TARGET.store(0, Ordering::SeqCst);
+        status
+    }
+
+    fn wait_for_stop(&mut self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
+        let options = options.unwrap_or_else(WaitPidFlag::empty) | WaitPidFlag::__WALL;
+        loop {
+            match waitpid(None, Some(options))? {
+                WaitStatus::Exited(tid, exit_code) => {
+                    if tid == self.pid {
+                        self.release_forks()?;
+                        return Ok(Status::Exited(exit_code));
+                    }
+                    self.remove_thread(tid)?;
+                }
+                WaitStatus::Signaled(tid, signal, _core_dumped) => {
+                    if tid == self.pid {
+                        self.release_forks()?;
+                        return Ok(Status::Signaled(signal));
+                    }
+                    self.remove_thread(tid)?;
+                }
+                WaitStatus::PtraceEvent(tid, _, libc::PTRACE_EVENT_CLONE) => {
+                    let new_tid = self.add_thread(tid)?;
+                    // When stepping, the new thread waits until everything is resumed
+                    self.resume_thread(tid)?;
+                    if !self.stepping {
+                        ptrace::cont(new_tid, None)?;
+                    }
+                }
+                WaitStatus::PtraceEvent(tid, _, libc::PTRACE_EVENT_FORK) => {
+                    self.follow_fork(tid, false)?;
+                }
+                WaitStatus::PtraceEvent(tid, _, libc::PTRACE_EVENT_VFORK) => {
+                    self.follow_fork(tid, true)?;
+                }
+                WaitStatus::PtraceEvent(tid, _, libc::PTRACE_EVENT_VFORK_DONE) => {
+                    // The child is done with our memory, so our breakpoints can go back in
+                    self.write_breakpoint_bytes(tid, true)?;
+                    self.resume_thread(tid)?;
+                }
+                WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_EXEC) => {
+                    self.release_vfork_parent()?;
+                    // The old program is gone, and with it our breakpoints and all other threads.
+                    // The thread that called exec (now the only one) takes over the process id.
+                    self.breakpoints.clear();
+                    self.threads.clear();
+                    self.threads.insert(1, self.pid);
+                    self.next_thread = 2;
+                    self.thread = self.pid;
+                    self.pending_signals.clear();
+                    self.rewound.clear();
+                    self.load_bias = compute_load_bias(self.pid)
+                        .map_err(|_| nix::Error::Sys(nix::errno::Errno::EIO))?;
+                    return Ok(Status::Exec);
+                }
+                WaitStatus::Stopped(tid, _) if !self.threads.values().any(|&t| t == tid) => {
+                    self.early_threads.insert(tid);
+                }
+                WaitStatus::Stopped(tid, signal) => {
+                    if tid != self.thread && self.threads.len() > 1 {
+                        println!(
+                            "[Switching to thread {} (LWP {})]",
+                            self.thread_number(tid),
+                            tid
+                        );
+                    }
+                    self.thread = tid;
+                    self.rewound.remove(&tid);
+                    if signal != signal::Signal::SIGTRAP {
+                        self.pending_signals.insert(tid, signal);
+                    }
+                    let rip = self.rewind_breakpoint(tid, signal)?;
+                    if !self.stepping {
+                        self.stop_other_threads()?;
+                    }
+                    return Ok(Status::Stopped(signal, rip));
+                }
+                // Other ptrace events (such as PTRACE_EVENT_STOP or PTRACE_EVENT_EXIT) and syscall
+                // stops are nothing we asked for, so the thread just carries on
+                WaitStatus::PtraceEvent(tid, _, _) | WaitStatus::PtraceSyscall(tid) => {
+                    self.resume_thread(tid)?;
+                }
+                WaitStatus::Continued(_) => {}
+                // Only happens with WNOHANG, when nothing has stopped yet
+                WaitStatus::StillAlive => {
+                    return Err(nix::Error::Sys(nix::errno::Errno::EAGAIN));
+                }
+            }
+        }
+    }
+
+    /// After hitting one of our breakpoints, rip points just past the 0xcc byte. Rewinds it so
+    /// that the thread (and anyone inspecting it) sees the real location, and returns that.
+    fn rewind_breakpoint(&self, tid: Pid, signal: signal::Signal) -> Result<usize, nix::Error> {
+        let mut regs = ptrace::getregs(tid)?;
+        let trap_addr = (regs.rip as usize).checked_sub(1);
+        if signal == signal::Signal::SIGTRAP
+            && trap_addr.is_some_and(|addr| self.breakpoints.contains_key(&addr))
+            && ptrace::getsiginfo(tid)?.si_code == libc::SI_KERNEL
+        {
+            regs.rip -= 1;
+            ptrace::setregs(tid, regs)?;
+        }
+        Ok(regs.rip as usize)
+    }
+
+    /// Stops every thread but the current one with SIGSTOP. A thread that hits a breakpoint
+    /// before the SIGSTOP arrives is rewound to hit it again once resumed; other signals it
+    /// receives are delivered once it is resumed. If it forks, we stay in the parent.
+    fn stop_other_threads(&mut self) -> Result<(), nix::Error> {
+        let others: Vec<Pid> = self
+            .threads
+            .values()
+            .cloned()
+            .filter(|&tid| tid != self.thread)
+            .collect();
+        for tid in others {
+            unsafe {
+                libc::syscall(
+                    libc::SYS_tgkill,
+                    self.pid.as_raw(),
+                    tid.as_raw(),
+                    libc::SIGSTOP,
+                );
+            }
+            loop {
+                match waitpid(tid, Some(WaitPidFlag::__WALL))? {
+                    WaitStatus::Stopped(_, signal::Signal::SIGSTOP) => break,
+                    WaitStatus::Stopped(_, signal::Signal::SIGTRAP) => {
+                        let rip = self.rewind_breakpoint(tid, signal::Signal::SIGTRAP)?;
+                        if self.breakpoints.contains_key(&rip) {
+                            self.rewound.insert(tid);
+                        }
+                    }
+                    WaitStatus::Stopped(_, signal) => {
+                        self.pending_signals.insert(tid, signal);
+                    }
+                    WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) => {
+                        // The new thread starts out stopped, and stays that way
+                        self.add_thread(tid)?;
+                    }
+                    WaitStatus::PtraceEvent(_, _, event)
+                        if event == libc::PTRACE_EVENT_FORK
+                            || event == libc::PTRACE_EVENT_VFORK =>
+                    {
+                        let child = self.wait_for_new_task(tid)?;
+                        self.leave_fork_child(child, event == libc::PTRACE_EVENT_VFORK)?;
+                    }
+                    WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_VFORK_DONE) => {
+                        self.write_breakpoint_bytes(tid, true)?;
+                    }
+                    WaitStatus::Exited(_, _) | WaitStatus::Signaled(_, _, _) => {
+                        self.remove_thread(tid)?;
+                        break;
+                    }
+                    _ => {}
+                }
+                ptrace::cont(tid, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Resumes a thread that reported an event the user doesn't need to hear about, the same way
+    /// it was running before.
+    fn resume_thread(&self, tid: Pid) -> Result<(), nix::Error> {
+        if self.stepping {
+            ptrace::step(tid, None)
+        } else {
+            ptrace::cont(tid, None)
+        }
+    }
+
+    /// Waits for the initial stop of a thread or process that `parent` just created, and returns
+    /// its id. It gets the same watchpoints as its parent.
+    fn wait_for_new_task(&mut self, parent: Pid) -> Result<Pid, nix::Error> {
+        let tid = Pid::from_raw(ptrace::getevent(parent)? as libc::pid_t);
+        if !self.early_threads.remove(&tid) {
+            waitpid(tid, Some(WaitPidFlag::__WALL))?;
+        }
+        for index in (0..4).chain(Some(7)) {
+            poke_debug_register(tid, index, peek_debug_register(parent, index)?)?;
+        }
+        Ok(tid)
+    }
+
+    /// Handles thread `tid` forking (or vforking): keeps debugging either the parent or the
+    /// child, and detaches from the other one or keeps it stopped, as configured.
+    fn follow_fork(&mut self, tid: Pid, vfork: bool) -> Result<(), nix::Error> {
+        let child = self.wait_for_new_task(tid)?;
+        if self.follow_fork_mode == FollowForkMode::Parent {
+            self.leave_fork_child(child, vfork)?;
+            return self.resume_thread(tid);
+        }
+
+        println!(
+            "[Attaching after {} to child process {}]",
+            if vfork { "vfork" } else { "fork" },
+            child
+        );
+        self.thread = tid;
+        if !self.stepping {
+            self.stop_other_threads()?;
+        }
+        let parent_threads: Vec<Pid> = self.threads.values().cloned().collect();
+        if !self.detach_on_fork {
+            println!("[Keeping parent process {} stopped]", self.pid);
+            self.suspended.extend(parent_threads);
+        } else if vfork {
+            self.vfork_parent = parent_threads;
+        } else {
+            println!("[Detaching after fork from parent process {}]", self.pid);
+            self.release_process(&parent_threads)?;
+        }
+        // The child only has a copy of the thread that forked
+        self.pid = child;
+        self.threads.clear();
+        self.threads.insert(1, child);
+        self.next_thread = 2;
+        self.thread = child;
+        self.pending_signals.clear();
+        self.rewound.clear();
+        self.resume_thread(child)
+    }
+
+    /// Detaches from (or keeps stopped, as configured) the child of a fork we stay in the parent
+    /// of.
+    fn leave_fork_child(&mut self, child: Pid, vfork: bool) -> Result<(), nix::Error> {
+        if !self.detach_on_fork {
+            println!("[Keeping child process {} stopped]", child);
+            self.suspended.push(child);
+            return Ok(());
+        }
+        // The child inherited our breakpoints. After a vfork, it even shares our memory, so they
+        // are put back once it is done with it.
+        self.write_breakpoint_bytes(child, false)?;
+        println!(
+            "[Detaching after {} from child process {}]",
+            if vfork { "vfork" } else { "fork" },
+            child
+        );
+        ptrace::detach(child, None)
+    }
+
+    /// Writes either our 0xcc bytes or the original instructions at every breakpoint, in the
+    /// memory of the process that thread `tid` belongs to.
+    fn write_breakpoint_bytes(&mut self, tid: Pid, inserted: bool) -> Result<(), nix::Error> {
+        let bytes: Vec<(usize, u8)> = self
+            .breakpoints
+            .iter()
+            .map(|(&addr, &orig_byte)| (addr, if inserted { 0xcc } else { orig_byte }))
+            .collect();
+        let thread = std::mem::replace(&mut self.thread, tid);
+        let result = bytes
+            .into_iter()
+            .try_for_each(|(addr, byte)| self.write_byte(addr, byte).map(drop));
+        self.thread = thread;
+        result
+    }
+
+    /// Detaches from the (stopped) threads of a process we no longer follow, taking our
+    /// breakpoints and watchpoints out of it first.
+    fn release_process(&mut self, threads: &[Pid]) -> Result<(), nix::Error> {
+        if let Some(&tid) = threads.first() {
+            self.write_breakpoint_bytes(tid, false)?;
+        }
+        for &tid in threads {
+            poke_debug_register(tid, 7, 0)?;
+            let signal = self.take_signal(tid);
+            ptrace::detach(tid, signal)?;
+        }
+        Ok(())
+    }
+
+    /// Detaches from the parent of the vfork we followed, if any, now that the child no longer
+    /// shares its memory.
+    fn release_vfork_parent(&mut self) -> Result<(), nix::Error> {
+        if let Some(&parent) = self.vfork_parent.first() {
+            println!("[Detaching after vfork from parent process {}]", parent);
+            let threads = std::mem::take(&mut self.vfork_parent);
+            self.release_process(&threads)?;
+        }
+        Ok(())
+    }
+
+    /// Detaches from the processes we kept stopped after forks, once we are done with the one
+    /// we followed.
+    fn release_forks(&mut self) -> Result<(), nix::Error> {
+        self.release_vfork_parent()?;
+        for tid in std::mem::take(&mut self.suspended) {
+            // Each of them has its own copy of our breakpoints
+            self.write_breakpoint_bytes(tid, false)?;
+            poke_debug_register(tid, 7, 0)?;
+            ptrace::detach(tid, None)?;
+        }
+        Ok(())
+    }
+
+    /// Records the thread that `parent` just created, waiting for its initial stop, and returns
+    /// its thread id.
+    fn add_thread(&mut self, parent: Pid) -> Result<Pid, nix::Error> {
+        let tid = self.wait_for_new_task(parent)?;
+        println!("[New thread {} (LWP {})]", self.next_thread, tid);
+        self.threads.insert(self.next_thread, tid);
+        self.next_thread += 1;
+        Ok(tid)
+    }
+
+    /// Forgets about a thread that exited. If it was being stepped, the step can't complete, so
+    /// the remaining threads are resumed instead.
+    fn remove_thread(&mut self, tid: Pid) -> Result<(), nix::Error> {
+        let num = self.thread_number(tid);
+        if num == 0 {
+            // Such as the other threads of a process that called exec
+            return Ok(());
+        }
+        println!("[Thread {} (LWP {}) exited]", num, tid);
+        self.threads.remove(&num);
+        self.pending_signals.remove(&tid);
+        self.rewound.remove(&tid);
+        if tid == self.thread {
+            self.thread = self.pid;
+            if self.stepping {
+                self.stepping = false;
+                let tids: Vec<Pid> = self.threads.values().cloned().collect();
+                for tid in tids {
+                    let signal = self.take_signal(tid);
+                    ptrace::cont(tid, signal)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{thread, time};
+
+    /// Ctrl+C reaches an inferior outside our process group (like an attached one) only through
+    /// the handler, and must come back as a SIGINT stop, which is reported as "Child stopped
+    /// (signal SIGINT)".
+    #[test]
+    fn test_interrupt_forwarded_to_attached_process() {
+        let mut child = Command::new("sleep")
+            .arg("10")
+            .process_group(0)
+            .spawn()
+            .expect("Could not start sleep");
+        // Attaching before sleep has finished starting up can report an extra SIGSTOP stop, so
+        // wait until it is actually sleeping
+        let stat = format!("/proc/{}/stat", child.id());
+        while !fs::read_to_string(&stat).unwrap().contains("(sleep) S") {
+            thread::sleep(time::Duration::from_millis(10));
+        }
+        let mut inferior =
+            Inferior::attach(Pid::from_raw(child.id() as i32)).expect("Could not attach");
+        // As by default in the debugger, the signals meant for it aren't passed on
+        inferior.set_signal_pass(signal::Signal::SIGSTOP, false);
+        inferior.set_signal_pass(signal::Signal::SIGINT, false);
+        install_interrupt_handler().expect("Could not install the SIGINT handler");
+        let interrupter = thread::spawn(|| {
+            thread::sleep(time::Duration::from_millis(200));
+            signal::kill(unistd::getpid(), signal::Signal::SIGINT).unwrap();
+        });
+        let status = inferior.continue_exec(&mut |_, _| None).unwrap();
+        interrupter.join().unwrap();
+        match status {
+            Status::Stopped(signal, _) => assert_eq!(signal, signal::Signal::SIGINT),
+            _ => panic!("The inferior didn't stop"),
+        }
+        signal::kill(inferior.pid(), signal::Signal::SIGKILL).unwrap();
+        child.wait().unwrap();
     }
 }
@@ -1,24 +1,39 @@
 mod debugger;
 mod debugger_command;
 mod dwarf_data;
+mod expression;
 mod gimli_wrapper;
 mod inferior;
 
 use crate::debugger::Debugger;
-use nix::sys::signal::{signal, SigHandler, Signal};
 use std::env;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
-        std::process::exit(1);
-    }
-    let target = &args[1];
+    let pid = match args.len() {
+        2 => None,
+        3 if args[1] == "--pid" => match args[2].parse::<i32>() {
+            Ok(pid) => Some(pid),
+            Err(_) => {
+                println!("Invalid pid {}", args[2]);
+                std::process::exit(1);
+            }
+        },
+        _ => {
+            println!("Usage: {} <target program> | --pid <pid>", args[0]);
+            std::process::exit(1);
+        }
+    };
 
-    // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
-    // processes)
-    unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
+    // Make ctrl+c interrupt the inferior rather than the debugger
+    inferior::install_interrupt_handler().expect("Error installing SIGINT handler");
 
-    Debugger::new(target).run();
+    match pid {
+        Some(pid) => {
+            let mut debugger = Debugger::new(&format!("/proc/{}/exe", pid));
+            debugger.attach(pid, None);
+            debugger.run();
+        }
+        None => Debugger::new(&args[1]).run(),
+    }
 }